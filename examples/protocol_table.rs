@@ -0,0 +1,7 @@
+//! Prints the type code table for the README.
+
+use sine_chat::frame::registry;
+
+fn main() {
+    print!("{}", registry::protocol_table(&registry::payloads()));
+}
@@ -0,0 +1,137 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, Ident, LitInt};
+
+/// Registers a type as a frame payload.
+///
+/// ```ignore
+/// #[derive(Serialize, Deserialize, Payload)]
+/// #[payload(code = 0x01, direction = client_to_server)]
+/// pub struct ClientMessage { ... }
+/// ```
+///
+/// Generates the `SendableSerdePayload`, `ReceivableSerdePayload` & `Payload` impls,
+/// plus `SendableBy`/`ReceivableBy` for the roles of the given direction.
+/// Two payloads with the same code in the same direction fail to compile with
+/// conflicting implementations of `frame::registry::Registered`.
+#[proc_macro_derive(Payload, attributes(payload))]
+pub fn derive_payload(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let attrs = PayloadAttrs::parse(&input)?;
+    let ident = &input.ident;
+    let name = ident.to_string();
+    let code = attrs.code;
+    let (direction, client_to_server, sender, receiver) = match attrs.direction.to_string().as_str()
+    {
+        "client_to_server" => (
+            quote!(ClientToServer),
+            true,
+            quote!(ClientSide),
+            quote!(ServerSide),
+        ),
+        "server_to_client" => (
+            quote!(ServerToClient),
+            false,
+            quote!(ServerSide),
+            quote!(ClientSide),
+        ),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &attrs.direction,
+                "expected `client_to_server` or `server_to_client`",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl crate::frame::Payload for #ident {
+            const INFO: crate::frame::registry::PayloadInfo = crate::frame::registry::PayloadInfo {
+                name: #name,
+                type_code: #code,
+                direction: crate::frame::registry::Direction::#direction,
+            };
+        }
+
+        impl crate::frame::SendableSerdePayload for #ident {
+            fn type_code(&self) -> u8 {
+                #code
+            }
+        }
+
+        impl crate::frame::ReceivableSerdePayload for #ident {
+            fn type_code() -> u8 {
+                #code
+            }
+        }
+
+        impl crate::frame::SendableBy<crate::frame::#sender> for #ident {}
+
+        impl crate::frame::ReceivableBy<crate::frame::#receiver> for #ident {}
+
+        impl crate::frame::registry::Registered<#client_to_server, #code>
+            for crate::frame::registry::Codes
+        {
+            const INFO: crate::frame::registry::PayloadInfo =
+                <#ident as crate::frame::Payload>::INFO;
+        }
+    })
+}
+
+/// Collects the `PayloadInfo` of every `#[derive(Payload)]` type of the crate, by
+/// probing each (direction, code) for a `Registered` impl.
+#[proc_macro]
+pub fn registered_payloads(_input: TokenStream) -> TokenStream {
+    let slots = [true, false].into_iter().flat_map(|client_to_server| {
+        (0..=u8::MAX).map(move |code| {
+            quote! {
+                payloads.extend(crate::frame::registry::Slot::<#client_to_server, #code>.info());
+            }
+        })
+    });
+    quote! {{
+        #[allow(unused_imports)]
+        use crate::frame::registry::{ViaRegistered as _, ViaUnregistered as _};
+        let mut payloads = Vec::new();
+        #(#slots)*
+        payloads
+    }}
+    .into()
+}
+
+struct PayloadAttrs {
+    code: u8,
+    direction: Ident,
+}
+
+impl PayloadAttrs {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut code = None;
+        let mut direction = None;
+        for attr in input.attrs.iter().filter(|a| a.path().is_ident("payload")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("code") {
+                    code = Some(meta.value()?.parse::<LitInt>()?.base10_parse::<u8>()?);
+                    Ok(())
+                } else if meta.path.is_ident("direction") {
+                    direction = Some(meta.value()?.parse::<Ident>()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `code` or `direction`"))
+                }
+            })?;
+        }
+        match (code, direction) {
+            (Some(code), Some(direction)) => Ok(Self { code, direction }),
+            _ => Err(syn::Error::new_spanned(
+                &input.ident,
+                "expected `#[payload(code = ..., direction = ...)]`",
+            )),
+        }
+    }
+}
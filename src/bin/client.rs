@@ -1,13 +1,18 @@
+use std::sync::Arc;
+
 use guard::guard;
 use sine_chat::{
-    frame,
-    message::{ClientMessage, Content, Handshake, HandshakeReply, MessageReply, ServerMessage},
+    frame::{self, messages::client::Inbound, ClientSide, Compression, Encoding},
+    message::{
+        Acknowledge, ClientInfo, ClientMessage, Content, ErrorCode, Handshake, HandshakeReply,
+        ReceiptKind, TypingState,
+    },
 };
 use tokio::{
     io::{stdin, AsyncBufReadExt, BufReader},
     net::TcpStream,
+    sync::Mutex,
 };
-use tokio_util::either::Either;
 
 const ADDR: &str = "127.0.0.1:8888";
 
@@ -15,16 +20,17 @@ const ADDR: &str = "127.0.0.1:8888";
 async fn main() -> anyhow::Result<()> {
     println!("Please input your name:");
     let user_name = input().await;
-    let (reader, writer) = connect_then_handshake(user_name).await?;
-    let send_task = tokio::spawn(send_message(writer));
-    receive_message(reader).await;
+    let (reader, writer) = connect_then_handshake(user_name.clone()).await?;
+    let writer = Arc::new(Mutex::new(writer));
+    let send_task = tokio::spawn(send_message(writer.clone()));
+    receive_message(reader, writer, user_name).await;
     println!("Disconnected");
     send_task.abort();
     Ok(())
 }
 
-type Reader = frame::Reader<tokio::net::tcp::OwnedReadHalf>;
-type Writer = frame::Writer<tokio::net::tcp::OwnedWriteHalf>;
+type Reader = frame::Reader<tokio::net::tcp::OwnedReadHalf, ClientSide>;
+type Writer = frame::Writer<tokio::net::tcp::OwnedWriteHalf, ClientSide>;
 
 async fn connect_then_handshake(user_name: String) -> anyhow::Result<(Reader, Writer)> {
     let stream = TcpStream::connect(&ADDR).await?;
@@ -34,7 +40,13 @@ async fn connect_then_handshake(user_name: String) -> anyhow::Result<(Reader, Wr
     let mut writer = Writer::new(writer);
 
     println!("Handshake ...");
-    let handshake = Handshake::new(user_name);
+    let mut handshake = Handshake::new(user_name);
+    handshake.client = Some(ClientInfo::new(
+        "sine_chat_cli".into(),
+        env!("CARGO_PKG_VERSION").into(),
+    ));
+    handshake.encodings = vec![Encoding::MessagePack, Encoding::Json];
+    handshake.compressions = vec![Compression::Deflate];
     writer.write(handshake).await?;
 
     let reply = reader
@@ -44,12 +56,17 @@ async fn connect_then_handshake(user_name: String) -> anyhow::Result<(Reader, Wr
         .unwrap_or(Err(anyhow::Error::msg("Expect handshake reply")))?;
 
     if reply.success {
+        reader.set_encoding(reply.encoding);
+        writer.set_encoding(reply.encoding);
+        reader.set_compression(reply.compression);
+        writer.set_compression(reply.compression);
         println!("Handshake completed");
         Ok((reader, writer))
     } else {
-        Err(anyhow::Error::msg(
-            reply.message.unwrap_or("Unknown".into()),
-        ))
+        Err(anyhow::Error::msg(describe_error(
+            reply.code,
+            reply.message,
+        )))
     }
 }
 
@@ -60,7 +77,8 @@ async fn input() -> String {
     input.trim().into()
 }
 
-async fn send_message(mut writer: Writer) {
+#[allow(clippy::diverging_sub_expression)]
+async fn send_message(writer: Arc<Mutex<Writer>>) {
     loop {
         let input = input().await;
         guard!(let Some((receiver, text)) = input.split_once("<")
@@ -70,28 +88,95 @@ async fn send_message(mut writer: Writer) {
         });
         // Send message.
         let message = ClientMessage::new(Content::Text(text.trim().into()), receiver.trim().into());
-        if let Err(err) = writer.write(message).await {
+        if let Err(err) = writer.lock().await.write(message).await {
             eprintln!("Sending error: {}", err);
         }
     }
 }
 
-async fn receive_message(mut reader: Reader) {
-    while let Some(msg) = reader.read_either::<ServerMessage, MessageReply>().await {
+async fn receive_message(mut reader: Reader, writer: Arc<Mutex<Writer>>, user_name: String) {
+    while let Some(msg) = reader.read_any().await {
         match msg {
             Ok(msg) => match msg {
-                Either::Left(msg) => {
-                    println!("[{} > {}] {}", msg.sender, msg.receiver, msg.content);
+                Inbound::ServerMessage(msg) => {
+                    let reply_to = msg
+                        .reply_to
+                        .map(|id| format!("(re #{}) ", id))
+                        .unwrap_or_default();
+                    println!(
+                        "[{} > {}] {}{}",
+                        msg.sender,
+                        msg.receiver,
+                        reply_to,
+                        msg.display_content()
+                    );
+                    if msg.receiver == user_name && msg.sender != user_name {
+                        // Printed messages count as read.
+                        acknowledge(&writer, msg.id).await;
+                    }
+                }
+                Inbound::PeerTyping(typing) if typing.state == TypingState::Typing => {
+                    println!("{} is typing…", typing.sender);
+                }
+                Inbound::Mention(mention) => {
+                    println!(
+                        "[#{} {} mentioned you, {} unread]",
+                        mention.id, mention.sender, mention.unread_mentions
+                    );
+                }
+                Inbound::Receipt(receipt) => {
+                    let kind = match receipt.kind {
+                        ReceiptKind::Delivered => "delivered to",
+                        ReceiptKind::Read => "read by",
+                    };
+                    println!("[#{} {} {}]", receipt.id, kind, receipt.by);
+                }
+                Inbound::MessageEdited(edited) => {
+                    println!("[#{} edited] {}", edited.id, edited.content);
+                }
+                Inbound::ReactionUpdated(updated) => {
+                    let reactions: Vec<_> = updated
+                        .reactions
+                        .iter()
+                        .map(|r| format!("{} {}", r.emoji, r.count))
+                        .collect();
+                    println!("[#{} reactions] {}", updated.id, reactions.join(" "));
+                }
+                Inbound::MessageDeleted(deleted) => {
+                    println!("[#{} deleted]", deleted.id);
+                }
+                Inbound::MessageExpired(expired) => {
+                    println!("[#{} expired]", expired.id);
                 }
-                Either::Right(reply) if !reply.success => {
+                Inbound::MessageReply(reply) if !reply.success => {
                     eprintln!(
                         "Sending error: {}",
-                        reply.message.unwrap_or("Unknown".into())
+                        describe_error(reply.code, reply.message)
                     );
                 }
+                Inbound::RateLimited(limited) => {
+                    eprintln!("Slow down, retry in {} ms", limited.retry_after);
+                }
                 _ => (),
             },
             Err(err) => eprintln!("Receiving error: {}", err),
         }
     }
 }
+
+async fn acknowledge(writer: &Mutex<Writer>, id: u64) {
+    let mut writer = writer.lock().await;
+    for kind in [ReceiptKind::Delivered, ReceiptKind::Read] {
+        if let Err(err) = writer.write(Acknowledge { kind, id }).await {
+            eprintln!("Sending error: {}", err);
+        }
+    }
+}
+
+fn describe_error(code: Option<ErrorCode>, message: Option<String>) -> String {
+    let code = code.unwrap_or(ErrorCode::Unknown);
+    match message {
+        Some(message) => format!("{} ({})", code, message),
+        None => code.to_string(),
+    }
+}
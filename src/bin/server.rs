@@ -1,9 +1,40 @@
-use sine_chat;
+use std::net::SocketAddr;
 
 const ADDR: &str = "127.0.0.1:8888";
+const HTTP_ADDR: &str = "127.0.0.1:8889";
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let options = Options::parse()?;
     println!("Running ...");
-    sine_chat::run_server(&ADDR).await
+    sine_chat::run_server(&ADDR, options.http_addr, options.public_url).await
+}
+
+#[derive(Debug)]
+struct Options {
+    /// Where blobs are served over HTTP, `--http-addr off` turns it off.
+    http_addr: Option<SocketAddr>,
+    public_url: Option<String>,
+}
+
+impl Options {
+    fn parse() -> anyhow::Result<Self> {
+        let mut options = Self {
+            http_addr: Some(HTTP_ADDR.parse()?),
+            public_url: None,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?;
+            match arg.as_str() {
+                "--http-addr" if value == "off" => options.http_addr = None,
+                "--http-addr" => options.http_addr = Some(value.parse()?),
+                "--public-url" => options.public_url = Some(value),
+                _ => anyhow::bail!("Unknown option: {}", arg),
+            }
+        }
+        Ok(options)
+    }
 }
@@ -0,0 +1,361 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use futures::future::join_all;
+use guard::guard;
+use sine_chat::{
+    frame::{self, messages::client::Inbound, ClientSide, Encoding},
+    handler::{Config, RateLimits},
+    message::{ClientMessage, Content, ErrorCode, Handshake, HandshakeReply},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time,
+};
+
+//
+// Usage: sine_bench [--clients N] [--rate MSG_PER_SEC] [--duration SECS] [--addr ADDR]
+//                   [--encoding json|message_pack]
+//
+// Without `--addr` an in-process server is started on an ephemeral loopback port.
+//
+
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Reader = frame::Reader<tokio::net::tcp::OwnedReadHalf, ClientSide>;
+type Writer = frame::Writer<tokio::net::tcp::OwnedWriteHalf, ClientSide>;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let options = Options::parse()?;
+    let addr = match options.addr {
+        Some(addr) => addr,
+        None => spawn_local_server().await?,
+    };
+    println!(
+        "Benchmarking {} with {} clients, {} msg/s for {}s ...",
+        addr,
+        options.clients,
+        options.rate,
+        options.duration.as_secs()
+    );
+
+    let stats = Arc::new(Stats::new());
+    let epoch = Instant::now();
+
+    // Step 1: connect & handshake
+    let connecting =
+        (0..options.clients).map(|idx| connect(addr, idx, options.encoding, stats.clone(), epoch));
+    let senders: Vec<_> = join_all(connecting).await.into_iter().flatten().collect();
+    if senders.len() < 2 {
+        anyhow::bail!("At least 2 connected clients are required");
+    }
+    println!("Connected: {}", senders.len());
+
+    // Step 2: send between random pairs at the target rate
+    let started = Instant::now();
+    let mut random = Random::new();
+    let mut ticker = time::interval(Duration::from_secs_f64(1.0 / options.rate as f64));
+    while started.elapsed() < options.duration {
+        ticker.tick().await;
+        let (from, to) = random.pair(senders.len());
+        let (sender, _) = &senders[from];
+        let (_, receiver) = &senders[to];
+        let text = (epoch.elapsed().as_micros() as u64).to_string();
+        let message = ClientMessage::new(Content::Text(text), receiver.clone());
+        if sender.try_send(message).is_ok() {
+            stats.sent.fetch_add(1, Ordering::Relaxed);
+        } else {
+            stats.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Step 3: wait for in-flight messages, then report
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    while stats.delivered.load(Ordering::Relaxed) < stats.sent.load(Ordering::Relaxed)
+        && Instant::now() < deadline
+    {
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    stats.report(started.elapsed());
+    Ok(())
+}
+
+async fn spawn_local_server() -> anyhow::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    // Every client connects from the loopback address.
+    let config = Config {
+        user_rate_limits: RateLimits::unlimited(),
+        ip_rate_limits: RateLimits::unlimited(),
+        max_connections: usize::MAX,
+        max_connections_per_ip: usize::MAX,
+        ..Default::default()
+    };
+    tokio::spawn(sine_chat::serve(listener, config));
+    Ok(addr)
+}
+
+// Client
+
+async fn connect(
+    addr: SocketAddr,
+    idx: usize,
+    encoding: Encoding,
+    stats: Arc<Stats>,
+    epoch: Instant,
+) -> Option<(mpsc::Sender<ClientMessage>, String)> {
+    let user_name = format!("bench-{}-{}", std::process::id(), idx);
+    let (reader, writer) = match handshake(addr, user_name.clone(), encoding).await {
+        Ok(pair) => pair,
+        Err(err) => {
+            eprintln!("Handshake error ({}): {}", user_name, err);
+            stats.handshake_failures.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+    };
+    let (sender, receiver) = mpsc::channel(64);
+    tokio::spawn(send_messages(writer, receiver));
+    tokio::spawn(receive_messages(reader, user_name.clone(), stats, epoch));
+    Some((sender, user_name))
+}
+
+async fn handshake(
+    addr: SocketAddr,
+    user_name: String,
+    encoding: Encoding,
+) -> anyhow::Result<(Reader, Writer)> {
+    let stream = TcpStream::connect(addr).await?;
+    stream.set_nodelay(true)?;
+
+    let (reader, writer) = stream.into_split();
+    let mut reader = Reader::new(reader);
+    let mut writer = Writer::new(writer);
+
+    let mut handshake = Handshake::new(user_name);
+    handshake.encodings = vec![encoding];
+    writer.write(handshake).await?;
+    let reply = reader
+        .read::<HandshakeReply>()
+        .await
+        .unwrap_or_else(|| Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()))?;
+
+    if reply.success {
+        reader.set_encoding(reply.encoding);
+        writer.set_encoding(reply.encoding);
+        reader.set_compression(reply.compression);
+        writer.set_compression(reply.compression);
+        Ok((reader, writer))
+    } else {
+        let code = reply.code.unwrap_or(ErrorCode::Unknown);
+        Err(anyhow::Error::msg(code))
+    }
+}
+
+async fn send_messages(mut writer: Writer, mut receiver: mpsc::Receiver<ClientMessage>) {
+    while let Some(message) = receiver.recv().await {
+        if writer.write(message).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[allow(clippy::diverging_sub_expression)]
+async fn receive_messages(
+    mut reader: Reader,
+    user_name: String,
+    stats: Arc<Stats>,
+    epoch: Instant,
+) {
+    while let Some(msg) = reader.read_any().await {
+        match msg {
+            // Every message is echoed to its sender as well, only count the receiving side.
+            Ok(Inbound::ServerMessage(msg))
+                if msg.receiver == user_name && msg.sender != user_name =>
+            {
+                guard!(let Content::Text(text) = msg.content else { continue });
+                guard!(let Ok(sent_at) = text.parse::<u64>() else { continue });
+                let now = epoch.elapsed().as_micros() as u64;
+                stats.record_delivery(now.saturating_sub(sent_at));
+            }
+            Ok(Inbound::MessageReply(reply)) if !reply.success => {
+                stats.send_failures.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(_) => (),
+            Err(_) => {
+                stats.receive_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+    stats.disconnects.fetch_add(1, Ordering::Relaxed);
+}
+
+// Stats
+
+#[derive(Debug)]
+struct Stats {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    delivered: AtomicU64,
+    send_failures: AtomicU64,
+    receive_errors: AtomicU64,
+    handshake_failures: AtomicU64,
+    disconnects: AtomicU64,
+    latencies: Mutex<Vec<u64>>,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Self {
+            sent: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            delivered: AtomicU64::new(0),
+            send_failures: AtomicU64::new(0),
+            receive_errors: AtomicU64::new(0),
+            handshake_failures: AtomicU64::new(0),
+            disconnects: AtomicU64::new(0),
+            latencies: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn record_delivery(&self, latency_micros: u64) {
+        self.latencies.lock().unwrap().push(latency_micros);
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn report(&self, elapsed: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        latencies.sort_unstable();
+        let delivered = self.delivered.load(Ordering::Relaxed);
+
+        println!("─────────────────────────────");
+        println!("Elapsed:            {:.2}s", elapsed.as_secs_f64());
+        println!("Sent:               {}", self.sent.load(Ordering::Relaxed));
+        println!(
+            "Dropped (backlog):  {}",
+            self.dropped.load(Ordering::Relaxed)
+        );
+        println!("Delivered:          {}", delivered);
+        println!(
+            "Throughput:         {:.1} msg/s",
+            delivered as f64 / elapsed.as_secs_f64()
+        );
+        println!(
+            "Latency p50:        {}",
+            format_micros(percentile(&latencies, 0.50))
+        );
+        println!(
+            "Latency p99:        {}",
+            format_micros(percentile(&latencies, 0.99))
+        );
+        println!(
+            "Send failures:      {}",
+            self.send_failures.load(Ordering::Relaxed)
+        );
+        println!(
+            "Receive errors:     {}",
+            self.receive_errors.load(Ordering::Relaxed)
+        );
+        println!(
+            "Handshake failures: {}",
+            self.handshake_failures.load(Ordering::Relaxed)
+        );
+        println!(
+            "Disconnects:        {}",
+            self.disconnects.load(Ordering::Relaxed)
+        );
+    }
+}
+
+fn percentile(sorted: &[u64], p: f64) -> Option<u64> {
+    let idx = (sorted.len().checked_sub(1)? as f64 * p).round() as usize;
+    Some(sorted[idx])
+}
+
+fn format_micros(micros: Option<u64>) -> String {
+    match micros {
+        Some(micros) => format!("{:.3}ms", micros as f64 / 1000.0),
+        None => "N/A".into(),
+    }
+}
+
+// Options
+
+#[derive(Debug)]
+struct Options {
+    clients: usize,
+    rate: u64,
+    duration: Duration,
+    addr: Option<SocketAddr>,
+    encoding: Encoding,
+}
+
+impl Options {
+    fn parse() -> anyhow::Result<Self> {
+        let mut options = Self {
+            clients: 1000,
+            rate: 1000,
+            duration: Duration::from_secs(10),
+            addr: None,
+            encoding: Encoding::Json,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?;
+            match arg.as_str() {
+                "--clients" => options.clients = value.parse()?,
+                "--rate" => options.rate = value.parse()?,
+                "--duration" => options.duration = Duration::from_secs(value.parse()?),
+                "--addr" => options.addr = Some(value.parse()?),
+                "--encoding" => options.encoding = serde_json::from_value(value.into())?,
+                _ => anyhow::bail!("Unknown option: {}", arg),
+            }
+        }
+        if options.rate == 0 {
+            anyhow::bail!("Rate must be positive");
+        }
+        Ok(options)
+    }
+}
+
+// Random
+
+/// Xorshift generator, good enough for picking random pairs.
+struct Random(u64);
+
+impl Random {
+    fn new() -> Self {
+        Self(seed() | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    fn pair(&mut self, len: usize) -> (usize, usize) {
+        let from = (self.next() % len as u64) as usize;
+        let offset = 1 + (self.next() % (len as u64 - 1)) as usize;
+        (from, (from + offset) % len)
+    }
+}
+
+fn seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
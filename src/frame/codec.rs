@@ -1,23 +1,34 @@
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
-use super::{Error, RawPayload};
+use super::{Compression, Error, RawPayload};
 
 //
-// ├───          Header (40)         ───┤
-// ┌────────────┬───────────────────────┬──────────────────┐
-// │  Type (8)  │  Payload Length (32)  │    Payload ...   │
-// └────────────┴───────────────────────┴──────────────────┘
+// ├───               Header (40)               ───┤
+// ┌────────────┬─────────┬────────────────────────┬──────────────────┐
+// │  Type (8)  │  C (1)  │  Payload Length (31)   │    Payload ...   │
+// └────────────┴─────────┴────────────────────────┴──────────────────┘
 //
+// C: the payload is compressed with the negotiated compression.
+//
+
+/// Default limit of payload length, applies to decompressed payloads as well.
+pub const DEFAULT_MAX_PAYLOAD_LENGTH: usize = 1024 * 1024;
+
+/// Payloads shorter than this are sent uncompressed.
+const COMPRESSION_THRESHOLD: usize = 256;
 
 #[derive(Debug)]
 pub struct Codec {
     state: State,
+    compression: Option<Compression>,
+    max_payload_length: usize,
 }
 
 #[derive(Debug, Clone, Copy)]
 struct Header {
     type_code: u8,
+    compressed: bool,
     payload_length: usize,
 }
 
@@ -25,20 +36,39 @@ impl Header {
     const TYPE_FIELD_LEN: usize = 1;
     const LENGTH_FIELD_LEN: usize = 4;
     const LEN: usize = Self::TYPE_FIELD_LEN + Self::LENGTH_FIELD_LEN;
+
+    const COMPRESSED_FLAG: u64 = 1 << 31;
+    const MAX_PAYLOAD_LENGTH: usize = (Self::COMPRESSED_FLAG - 1) as usize;
 }
 
 #[derive(Debug, Clone, Copy)]
 enum State {
     Header,
     Payload(Header),
+    /// Discarding the remaining bytes of an oversized payload.
+    Skip(usize),
 }
 
 impl Codec {
     pub fn new() -> Self {
         Self {
             state: State::Header,
+            compression: None,
+            max_payload_length: DEFAULT_MAX_PAYLOAD_LENGTH,
         }
     }
+
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.compression = compression;
+    }
+
+    pub fn max_payload_length(&self) -> usize {
+        self.max_payload_length
+    }
+
+    pub fn set_max_payload_length(&mut self, max_payload_length: usize) {
+        self.max_payload_length = max_payload_length.min(Header::MAX_PAYLOAD_LENGTH);
+    }
 }
 
 impl Default for Codec {
@@ -51,39 +81,65 @@ impl Decoder for Codec {
     type Item = RawPayload;
     type Error = Error;
 
+    #[allow(clippy::diverging_sub_expression)]
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let payload = self
-            .decode_header(src)
-            .and_then(|header| self.decode_payload(header, src));
-        Ok(payload)
+        if !self.skip(src) {
+            return Ok(None);
+        }
+        guard::guard!(let Some(header) = self.decode_header(src)? else { return Ok(None) });
+        guard::guard!(let Some(content) = self.decode_payload(header, src) else { return Ok(None) });
+        self.decompress(header, content)
+            .map(|content| Some(RawPayload::new(header.type_code, content)))
     }
 }
 
 impl Codec {
-    fn decode_header(&mut self, src: &mut BytesMut) -> Option<Header> {
+    /// Returns whether the pending skip has finished.
+    fn skip(&mut self, src: &mut BytesMut) -> bool {
+        if let State::Skip(remaining) = self.state {
+            let len = remaining.min(src.len());
+            src.advance(len);
+            if len < remaining {
+                self.state = State::Skip(remaining - len);
+                return false;
+            }
+            self.state = State::Header;
+        }
+        true
+    }
+
+    fn decode_header(&mut self, src: &mut BytesMut) -> Result<Option<Header>, Error> {
         if let State::Payload(header) = self.state {
-            return Some(header);
+            return Ok(Some(header));
         }
 
         if src.len() < Header::LEN {
-            return None;
+            return Ok(None);
         }
 
         // Gets type & length from bytes in network (big) endian byte order.
         let type_code = src.get_uint(Header::TYPE_FIELD_LEN) as u8;
-        let payload_length = src.get_uint(Header::LENGTH_FIELD_LEN) as usize;
+        let length_field = src.get_uint(Header::LENGTH_FIELD_LEN);
         let header = Header {
             type_code,
-            payload_length,
+            compressed: length_field & Header::COMPRESSED_FLAG != 0,
+            payload_length: (length_field & !Header::COMPRESSED_FLAG) as usize,
         };
 
+        if header.payload_length > self.max_payload_length {
+            // Drops the payload without buffering it, the `Reader` carries on after the error.
+            self.state = State::Skip(header.payload_length);
+            self.skip(src);
+            return Err(Error::TooLarge(header.payload_length));
+        }
+
         src.reserve(header.payload_length);
         self.state = State::Payload(header);
 
-        Some(header)
+        Ok(Some(header))
     }
 
-    fn decode_payload(&mut self, header: Header, src: &mut BytesMut) -> Option<RawPayload> {
+    fn decode_payload(&mut self, header: Header, src: &mut BytesMut) -> Option<Bytes> {
         if src.len() < header.payload_length {
             return None;
         }
@@ -92,7 +148,24 @@ impl Codec {
         src.reserve(Header::LEN);
         self.state = State::Header;
 
-        Some(RawPayload::new(header.type_code, bytes.freeze()))
+        Some(bytes.freeze())
+    }
+
+    fn decompress(&self, header: Header, content: Bytes) -> Result<Bytes, Error> {
+        if !header.compressed {
+            return Ok(content);
+        }
+        let compression = self.compression.ok_or_else(|| {
+            Error::Compression(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Compression not negotiated",
+            ))
+        })?;
+        compression
+            .decompress(&content, self.max_payload_length)
+            .map_err(Error::Compression)?
+            .map(Bytes::from)
+            .ok_or(Error::TooLarge(self.max_payload_length + 1))
     }
 }
 
@@ -100,14 +173,40 @@ impl Encoder<RawPayload> for Codec {
     type Error = Error;
 
     fn encode(&mut self, item: RawPayload, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let payload_length = item.content.len();
+        if item.content.len() > self.max_payload_length {
+            return Err(Error::TooLarge(item.content.len()));
+        }
+        let (compressed, content) = self.compress(item.content)?;
+        let payload_length = content.len();
         dst.reserve(Header::LEN + payload_length);
 
+        let mut length_field = payload_length as u64;
+        if compressed {
+            length_field |= Header::COMPRESSED_FLAG;
+        }
+
         // Writes type & length to bytes in network (big) endian byte order.
         dst.put_uint(item.type_code as u64, Header::TYPE_FIELD_LEN);
-        dst.put_uint(payload_length as u64, Header::LENGTH_FIELD_LEN);
-        dst.put_slice(&item.content);
+        dst.put_uint(length_field, Header::LENGTH_FIELD_LEN);
+        dst.put_slice(&content);
 
         Ok(())
     }
 }
+
+impl Codec {
+    /// Returns the content to send and whether it's compressed.
+    #[allow(clippy::diverging_sub_expression)]
+    fn compress(&self, content: Bytes) -> Result<(bool, Bytes), Error> {
+        guard::guard!(let Some(compression) = self.compression else { return Ok((false, content)) });
+        if content.len() < COMPRESSION_THRESHOLD {
+            return Ok((false, content));
+        }
+        let compressed = compression.compress(&content).map_err(Error::Compression)?;
+        // Incompressible content goes out as is.
+        if compressed.len() >= content.len() {
+            return Ok((false, content));
+        }
+        Ok((true, compressed.into()))
+    }
+}
@@ -0,0 +1,57 @@
+use std::io::{Error as IoError, ErrorKind, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+/// Per-frame payload compression, negotiated per connection in the handshake.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    Deflate,
+    /// A compression introduced by a newer peer, never chosen.
+    #[serde(other)]
+    Unknown,
+}
+
+impl Compression {
+    pub const SUPPORTED: &'static [Compression] = &[Compression::Deflate];
+
+    pub fn is_supported(self) -> bool {
+        Self::SUPPORTED.contains(&self)
+    }
+
+    /// Picks the first supported compression the peer offered, if any.
+    pub fn negotiate(offered: &[Compression]) -> Option<Self> {
+        offered.iter().copied().find(|c| c.is_supported())
+    }
+
+    pub(crate) fn compress(self, bytes: &[u8]) -> Result<Vec<u8>, IoError> {
+        match self {
+            Self::Deflate => {
+                let mut encoder =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            Self::Unknown => Err(Self::unsupported()),
+        }
+    }
+
+    /// Decompresses at most `limit` bytes, `None` if the content exceeds it.
+    pub(crate) fn decompress(self, bytes: &[u8], limit: usize) -> Result<Option<Vec<u8>>, IoError> {
+        let mut decompressed = Vec::new();
+        match self {
+            Self::Deflate => {
+                // Reads one byte past the limit to tell "exactly at" from "over" it.
+                flate2::read::DeflateDecoder::new(bytes)
+                    .take(limit as u64 + 1)
+                    .read_to_end(&mut decompressed)?;
+            }
+            Self::Unknown => return Err(Self::unsupported()),
+        }
+        Ok((decompressed.len() <= limit).then_some(decompressed))
+    }
+
+    fn unsupported() -> IoError {
+        IoError::new(ErrorKind::Unsupported, "Unsupported compression")
+    }
+}
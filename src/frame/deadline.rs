@@ -0,0 +1,137 @@
+use std::{
+    future::Future,
+    io, mem,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    time::{self, Instant, Sleep},
+};
+
+/// How fast a frame has to arrive once its first byte has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReceiveLimits {
+    /// Longest time from the first byte of a frame to its last.
+    pub timeout: Duration,
+    /// Average bytes per second a frame has to arrive at after its first second,
+    /// 0 for no minimum.
+    pub min_throughput: u64,
+}
+
+impl Default for ReceiveLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(60),
+            min_throughput: 1024,
+        }
+    }
+}
+
+/// Time any frame gets regardless of throughput, so its first bytes may trickle in.
+const THROUGHPUT_GRACE: Duration = Duration::from_secs(1);
+
+/// Reads from `T` until a partially received frame runs out of time, then fails.
+///
+/// Waiting for a frame to start is never limited.
+#[derive(Debug)]
+pub struct Deadline<T> {
+    inner: T,
+    limits: ReceiveLimits,
+    /// When the frame in progress started arriving, and how many bytes of it have.
+    frame: Option<(Instant, u64)>,
+    /// Bytes of the next frame read along with the last one.
+    buffered: u64,
+    sleep: Option<Pin<Box<Sleep>>>,
+    expired: bool,
+}
+
+impl<T> Deadline<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            limits: ReceiveLimits::default(),
+            frame: None,
+            buffered: 0,
+            sleep: None,
+            expired: false,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: ReceiveLimits) {
+        self.limits = limits;
+    }
+
+    /// Whether a frame ran out of time, after which every read fails.
+    pub fn is_expired(&self) -> bool {
+        self.expired
+    }
+
+    /// Ends the frame in progress, `buffered` bytes of the next one are already read.
+    ///
+    /// The next frame's time starts once it's read from again, so however long the
+    /// reader takes to come back isn't held against the peer.
+    pub fn finish_frame(&mut self, buffered: usize) {
+        self.frame = None;
+        self.buffered = buffered as u64;
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        let (started, received) = self.frame?;
+        let timeout = started + self.limits.timeout;
+        if self.limits.min_throughput == 0 {
+            return Some(timeout);
+        }
+        let earned = Duration::from_secs_f64(received as f64 / self.limits.min_throughput as f64);
+        Some(timeout.min(started + THROUGHPUT_GRACE + earned))
+    }
+
+    /// Whether the deadline has passed, otherwise wakes the task when it does.
+    #[allow(clippy::diverging_sub_expression)]
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> bool {
+        guard::guard!(let Some(deadline) = self.deadline() else { return false });
+        if Instant::now() >= deadline {
+            return true;
+        }
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(time::sleep_until(deadline)));
+        if sleep.deadline() != deadline {
+            sleep.as_mut().reset(deadline);
+        }
+        sleep.as_mut().poll(cx).is_ready()
+    }
+}
+
+impl<T> AsyncRead for Deadline<T>
+where
+    T: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.frame.is_none() && this.buffered > 0 {
+            this.frame = Some((Instant::now(), mem::take(&mut this.buffered)));
+        }
+        if !this.expired {
+            let filled = buf.filled().len();
+            let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+            let read = (buf.filled().len() - filled) as u64;
+            if read > 0 {
+                let (_, received) = this.frame.get_or_insert((Instant::now(), 0));
+                *received += read;
+            }
+            if matches!(poll, Poll::Ready(Err(_))) || !this.poll_expired(cx) {
+                return poll;
+            }
+            this.expired = true;
+        }
+        let err = io::Error::new(io::ErrorKind::TimedOut, "Frame received too slowly");
+        Poll::Ready(Err(err))
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt::Display;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// How payloads are encoded, negotiated per connection in the handshake.
+///
+/// The handshake itself is always encoded as JSON.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Json,
+    MessagePack,
+    /// An encoding introduced by a newer peer, never chosen.
+    #[serde(other)]
+    Unknown,
+}
+
+impl Encoding {
+    pub const SUPPORTED: &'static [Encoding] = &[Encoding::Json, Encoding::MessagePack];
+
+    pub fn is_supported(self) -> bool {
+        Self::SUPPORTED.contains(&self)
+    }
+
+    /// Picks the first supported encoding the peer offered, JSON if none.
+    pub fn negotiate(offered: &[Encoding]) -> Self {
+        offered
+            .iter()
+            .copied()
+            .find(|e| e.is_supported())
+            .unwrap_or_default()
+    }
+
+    pub fn serialize<T>(self, value: &T) -> Result<Vec<u8>, CodingError>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.into()),
+            // Named fields keep optional fields addable without breaking older peers.
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.into()),
+            Self::Unknown => Err(CodingError::Unsupported),
+        }
+    }
+
+    pub fn deserialize<T>(self, bytes: &[u8]) -> Result<T, CodingError>
+    where
+        T: DeserializeOwned,
+    {
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(|e| e.into()),
+            Self::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.into()),
+            Self::Unknown => Err(CodingError::Unsupported),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CodingError {
+    Json(serde_json::Error),
+    MessagePackEncode(rmp_serde::encode::Error),
+    MessagePackDecode(rmp_serde::decode::Error),
+    Unsupported,
+}
+
+impl From<serde_json::Error> for CodingError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl From<rmp_serde::encode::Error> for CodingError {
+    fn from(err: rmp_serde::encode::Error) -> Self {
+        Self::MessagePackEncode(err)
+    }
+}
+
+impl From<rmp_serde::decode::Error> for CodingError {
+    fn from(err: rmp_serde::decode::Error) -> Self {
+        Self::MessagePackDecode(err)
+    }
+}
+
+impl Display for CodingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "JSON: {}", err),
+            Self::MessagePackEncode(err) => write!(f, "MessagePack: {}", err),
+            Self::MessagePackDecode(err) => write!(f, "MessagePack: {}", err),
+            Self::Unsupported => f.write_str("Unsupported encoding"),
+        }
+    }
+}
+
+impl std::error::Error for CodingError {}
@@ -1,65 +1,62 @@
 use crate::message::{
-    ClientMessage, Handshake, HandshakeReply, MessageReply, Ping, Pong, ServerMessage,
+    Acknowledge, AddReaction, CancelScheduled, ClientMessage, DeleteMessage, DownloadRequest,
+    EditMessage, Handshake, HistoryRequest, ListScheduled, Ping, RemoveReaction, ScheduleMessage,
+    Typing, UploadChunk, UploadRequest,
 };
 
-use super::{ReceivableJSONPayload, SendableJSONPayload};
-
-//
-//       Code     │   From Client   │  From Server
-//  ──────────────┼─────────────────┼────────────────
-//       0x00     │   Handshake     │ HandshakeReply
-//                │                 │
-//       0x01     │  ClientMessage  │  ServerMessage
-//                │                 │
-//       0x02     │      N/A        │  MessageReply
-//                │                 │
-//       0xFF     │      Ping       │     Pong
 //
-//  0x03 ~ 0xFE: reserved
+// Type codes are registered with `#[derive(Payload)]` on each message, see
+// `registry::payloads()` for the resulting table.
 //
 
-macro_rules! impl_payload {
-    (sendable: $msg_ident:ident > $type_code:expr) => {
-        impl SendableJSONPayload for $msg_ident {
-            fn type_code(&self) -> u8 {
-                $type_code
-            }
-        }
-    };
-    (receivable: $msg_ident:ident > $type_code:expr) => {
-        impl ReceivableJSONPayload for $msg_ident {
-            fn type_code() -> u8 {
-                $type_code
-            }
-        }
-    };
+crate::receivable_enum! {
+    /// Every payload a server receives.
+    pub enum Inbound {
+        Handshake(Handshake),
+        ClientMessage(ClientMessage),
+        Ping(Ping),
+        UploadRequest(UploadRequest),
+        UploadChunk(UploadChunk),
+        DownloadRequest(DownloadRequest),
+        EditMessage(EditMessage),
+        DeleteMessage(DeleteMessage),
+        HistoryRequest(HistoryRequest),
+        Acknowledge(Acknowledge),
+        Typing(Typing),
+        AddReaction(AddReaction),
+        RemoveReaction(RemoveReaction),
+        ScheduleMessage(ScheduleMessage),
+        ListScheduled(ListScheduled),
+        CancelScheduled(CancelScheduled),
+    }
 }
 
-impl_payload!(receivable: Handshake > 0x00);
-impl_payload!(sendable: HandshakeReply > 0x00);
-
-impl_payload!(receivable: ClientMessage > 0x01);
-impl_payload!(sendable: ServerMessage > 0x01);
-
-impl_payload!(sendable: MessageReply > 0x02);
-
-impl_payload!(receivable: Ping > 0xFF);
-impl_payload!(sendable: Pong > 0xFF);
-
 pub mod client {
-    use super::{ReceivableJSONPayload, SendableJSONPayload};
     use crate::message::{
-        ClientMessage, Handshake, HandshakeReply, MessageReply, Ping, Pong, ServerMessage,
+        DownloadChunk, HandshakeReply, HistoryReply, Mention, MessageDeleted, MessageEdited,
+        MessageExpired, MessageReply, PeerTyping, Pong, RateLimited, ReactionUpdated, Receipt,
+        ScheduledList, ServerMessage, UploadReply,
     };
 
-    impl_payload!(sendable: Handshake > 0x00);
-    impl_payload!(receivable: HandshakeReply > 0x00);
-
-    impl_payload!(sendable: ClientMessage > 0x01);
-    impl_payload!(receivable: ServerMessage > 0x01);
-
-    impl_payload!(receivable: MessageReply > 0x02);
-
-    impl_payload!(sendable: Ping > 0xFF);
-    impl_payload!(receivable: Pong > 0xFF);
+    crate::receivable_enum! {
+        /// Every payload a client receives.
+        pub enum Inbound {
+            HandshakeReply(HandshakeReply),
+            ServerMessage(ServerMessage),
+            MessageReply(MessageReply),
+            Pong(Pong),
+            UploadReply(UploadReply),
+            DownloadChunk(DownloadChunk),
+            MessageEdited(MessageEdited),
+            MessageDeleted(MessageDeleted),
+            HistoryReply(HistoryReply),
+            Receipt(Receipt),
+            PeerTyping(PeerTyping),
+            ReactionUpdated(ReactionUpdated),
+            MessageExpired(MessageExpired),
+            ScheduledList(ScheduledList),
+            Mention(Mention),
+            RateLimited(RateLimited),
+        }
+    }
 }
@@ -4,18 +4,35 @@ use std::io::Error as IoError;
 mod codec;
 use tokio::io::{AsyncRead, AsyncWrite};
 
-pub use self::codec::Codec;
+pub use self::codec::{Codec, DEFAULT_MAX_PAYLOAD_LENGTH};
+
+mod deadline;
+pub use self::deadline::ReceiveLimits;
+
+mod compression;
+pub use self::compression::Compression;
 
 mod payload;
 pub use self::payload::{
-    RawPayload, ReceivableJSONPayload, ReceivablePayload, SendableJSONPayload, SendablePayload,
+    RawPayload, ReceivableEnum, ReceivablePayload, ReceivableSerdePayload, SendablePayload,
+    SendableSerdePayload,
 };
 
+mod encoding;
+pub use self::encoding::{CodingError, Encoding};
+
 mod reader_writer;
 pub use self::reader_writer::{Reader, Writer};
 
+mod role;
+pub use self::role::{ClientSide, ReceivableBy, Role, SendableBy, ServerSide};
+
 pub mod messages;
 
+pub mod registry;
+pub use self::registry::Payload;
+pub use sine_chat_derive::Payload;
+
 pub type FramedRead<T> = tokio_util::codec::FramedRead<T, Codec>;
 pub type FramedWrite<T> = tokio_util::codec::FramedWrite<T, Codec>;
 
@@ -39,7 +56,12 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     Io(IoError),
     TypeMismatch(u8),
-    Coding(serde_json::Error),
+    WrongDirection(u8),
+    TooLarge(usize),
+    Compression(IoError),
+    Coding(CodingError),
+    /// A frame took longer than the `ReceiveLimits` allow, the reader is closed.
+    SlowFrame,
 }
 
 impl From<IoError> for Error {
@@ -48,8 +70,8 @@ impl From<IoError> for Error {
     }
 }
 
-impl From<serde_json::Error> for Error {
-    fn from(err: serde_json::Error) -> Self {
+impl From<CodingError> for Error {
+    fn from(err: CodingError) -> Self {
         Self::Coding(err)
     }
 }
@@ -59,7 +81,11 @@ impl Display for Error {
         let str = match self {
             Self::Io(err) => format!("IO error: {}", err),
             Self::TypeMismatch(type_code) => format!("Type mismatch: {}", type_code),
+            Self::WrongDirection(type_code) => format!("Wrong direction: {}", type_code),
+            Self::TooLarge(length) => format!("Payload too large: {}", length),
+            Self::Compression(err) => format!("Compression error: {}", err),
             Self::Coding(err) => format!("Coding error: {}", err),
+            Self::SlowFrame => "Frame received too slowly".to_string(),
         };
         f.write_str(&str)
     }
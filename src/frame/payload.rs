@@ -3,7 +3,7 @@ use std::fmt::Debug;
 use bytes::Bytes;
 use serde::{de::DeserializeOwned, Serialize};
 
-use super::{Error, Result};
+use super::{registry::PayloadInfo, Encoding, Error, Result};
 
 #[derive(Debug)]
 pub struct RawPayload {
@@ -16,62 +16,133 @@ impl RawPayload {
         Self { type_code, content }
     }
 
-    pub fn from_payload(payload: impl SendablePayload) -> Result<Self> {
-        payload.as_raw()
+    pub fn from_payload(payload: impl SendablePayload, encoding: Encoding) -> Result<Self> {
+        payload.as_raw(encoding)
     }
 
-    pub fn into_payload<T>(&self) -> Result<T>
+    pub fn into_payload<T>(&self, encoding: Encoding) -> Result<T>
     where
         T: ReceivablePayload,
     {
-        T::from_raw(&self)
+        T::from_raw(self, encoding)
     }
 }
 
 pub trait ReceivablePayload: Sized {
-    fn from_raw(raw: &RawPayload) -> Result<Self>;
+    fn accepts(type_code: u8) -> bool;
+    fn from_raw(raw: &RawPayload, encoding: Encoding) -> Result<Self>;
+}
+
+/// An enum of several receivable payloads, see [`receivable_enum!`](crate::receivable_enum).
+pub trait ReceivableEnum: ReceivablePayload {
+    const PAYLOADS: &'static [PayloadInfo];
 }
 
 pub trait SendablePayload: Send {
-    fn as_raw(&self) -> Result<RawPayload>;
+    fn as_raw(&self, encoding: Encoding) -> Result<RawPayload>;
 }
 
 impl SendablePayload for Box<dyn SendablePayload> {
-    fn as_raw(&self) -> Result<RawPayload> {
-        self.as_ref().as_raw()
+    fn as_raw(&self, encoding: Encoding) -> Result<RawPayload> {
+        self.as_ref().as_raw(encoding)
     }
 }
 
-// JSON
+// Serde
 
-pub trait ReceivableJSONPayload: DeserializeOwned + Debug {
+pub trait ReceivableSerdePayload: DeserializeOwned + Debug {
     fn type_code() -> u8;
 }
 
 impl<T> ReceivablePayload for T
 where
-    T: ReceivableJSONPayload,
+    T: ReceivableSerdePayload,
 {
-    fn from_raw(raw: &RawPayload) -> Result<Self> {
+    fn accepts(type_code: u8) -> bool {
+        Self::type_code() == type_code
+    }
+
+    fn from_raw(raw: &RawPayload, encoding: Encoding) -> Result<Self> {
         if Self::type_code() == raw.type_code {
-            serde_json::from_slice(&raw.content).map_err(|e| e.into())
+            encoding.deserialize(&raw.content).map_err(|e| e.into())
         } else {
             Err(Error::TypeMismatch(raw.type_code))
         }
     }
 }
 
-pub trait SendableJSONPayload: Serialize + Send {
+pub trait SendableSerdePayload: Serialize + Send {
     fn type_code(&self) -> u8;
 }
 
 impl<T> SendablePayload for T
 where
-    T: SendableJSONPayload,
+    T: SendableSerdePayload,
 {
-    fn as_raw(&self) -> Result<RawPayload> {
-        serde_json::to_vec(self)
+    fn as_raw(&self, encoding: Encoding) -> Result<RawPayload> {
+        encoding
+            .serialize(self)
             .map(|content| RawPayload::new(self.type_code(), content.into()))
             .map_err(|e| e.into())
     }
 }
+
+// Dispatch
+
+/// Declares an enum over receivable payloads which is decoded in a single pass,
+/// choosing the variant by the type code of the frame.
+#[macro_export]
+macro_rules! receivable_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $enum_ident:ident {
+            $($variant:ident($payload:ty)),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug)]
+        $vis enum $enum_ident {
+            $($variant($payload)),+
+        }
+
+        impl $crate::frame::ReceivablePayload for $enum_ident {
+            fn accepts(type_code: u8) -> bool {
+                $(<$payload as $crate::frame::ReceivablePayload>::accepts(type_code))||+
+            }
+
+            fn from_raw(
+                raw: &$crate::frame::RawPayload,
+                encoding: $crate::frame::Encoding,
+            ) -> $crate::frame::Result<Self> {
+                $(
+                    if <$payload as $crate::frame::ReceivablePayload>::accepts(raw.type_code) {
+                        return <$payload as $crate::frame::ReceivablePayload>::from_raw(raw, encoding)
+                            .map(Self::$variant);
+                    }
+                )+
+                Err($crate::frame::Error::TypeMismatch(raw.type_code))
+            }
+        }
+
+        impl<R> $crate::frame::ReceivableBy<R> for $enum_ident
+        where
+            R: $crate::frame::Role,
+            $($payload: $crate::frame::ReceivableBy<R>),+
+        {
+        }
+
+        impl $crate::frame::ReceivableEnum for $enum_ident {
+            const PAYLOADS: &'static [$crate::frame::registry::PayloadInfo] =
+                &[$(<$payload as $crate::frame::Payload>::INFO),+];
+        }
+
+        impl $enum_ident {
+            /// Type code of the frame the payload came in.
+            pub fn type_code(&self) -> u8 {
+                match self {
+                    $(Self::$variant(_) => <$payload as $crate::frame::Payload>::INFO.type_code),+
+                }
+            }
+        }
+    };
+}
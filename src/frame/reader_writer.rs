@@ -1,96 +1,166 @@
+use std::marker::PhantomData;
+
+use bytes::BytesMut;
 use futures::{SinkExt, StreamExt};
 use tokio::io::{AsyncRead, AsyncWrite};
-use tokio_util::either::Either;
+use tokio_util::codec::{Decoder, FramedRead};
 
 use super::{
-    new_framed_read, new_framed_write, Error, FramedRead, FramedWrite, RawPayload,
-    ReceivablePayload, Result, SendablePayload,
+    deadline::Deadline, new_framed_write, Codec, Compression, Encoding, Error, FramedWrite,
+    RawPayload, ReceivableBy, ReceivablePayload, ReceiveLimits, Result, Role, SendableBy,
 };
 
-pub struct Reader<T>
+pub struct Reader<T, R>
 where
     T: AsyncRead,
 {
-    inner: FramedRead<T>,
-    peeked: Option<RawPayload>,
+    inner: FramedRead<Deadline<T>, Resuming>,
+    encoding: Encoding,
+    role: PhantomData<R>,
 }
 
-impl<T> Reader<T>
+impl<T, R> Reader<T, R>
 where
     T: AsyncRead + Send + Unpin,
+    R: Role,
 {
     pub fn new(inner: T) -> Self {
         Self {
-            inner: new_framed_read(inner),
-            peeked: None,
+            inner: FramedRead::new(Deadline::new(inner), Resuming(Codec::new())),
+            encoding: Encoding::default(),
+            role: PhantomData,
         }
     }
 
-    pub async fn read_either<L, R>(&mut self) -> Option<Result<Either<L, R>>>
-    where
-        L: ReceivablePayload,
-        R: ReceivablePayload,
-    {
-        if let Some(left) = self
-            .read_if_match_type::<L>()
-            .await
-            .map(|x| x.map(Either::Left))
-        {
-            return Some(left);
-        }
-        self.read::<R>().await.map(|x| x.map(Either::Right))
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Switches the encoding of subsequent payloads, once negotiated.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Limits how long each frame may take to arrive, against peers trickling them in.
+    pub fn set_receive_limits(&mut self, limits: ReceiveLimits) {
+        self.inner.get_mut().set_limits(limits);
+    }
+
+    /// Accepts compressed frames, once negotiated.
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.inner.decoder_mut().0.set_compression(compression);
+    }
+
+    pub fn set_max_payload_length(&mut self, max_payload_length: usize) {
+        self.inner
+            .decoder_mut()
+            .0
+            .set_max_payload_length(max_payload_length);
+    }
+
+    /// Reads the next frame as whichever inbound payload its type code selects.
+    pub async fn read_any(&mut self) -> Option<Result<R::Inbound>> {
+        self.read::<R::Inbound>().await
     }
 
     pub async fn read<P>(&mut self) -> Option<Result<P>>
     where
-        P: ReceivablePayload,
+        P: ReceivableBy<R>,
     {
         self.read_raw()
             .await
-            .map(|r| r.and_then(|x| x.into_payload()))
+            .map(|r| r.and_then(|x| x.into_payload(self.encoding)))
     }
 
-    pub async fn read_if_match_type<P>(&mut self) -> Option<Result<P>>
-    where
-        P: ReceivablePayload,
-    {
-        match self.read_raw().await? {
-            Ok(raw) => {
-                let payload = raw.into_payload();
-                if !matches!(payload, Err(Error::TypeMismatch(_))) {
-                    Some(payload)
-                } else {
-                    self.peeked = Some(raw);
-                    None
-                }
-            }
-            Err(err) => Some(Err(err)),
+    /// Reads the next frame, rejecting type codes only the peer may receive.
+    ///
+    /// Once a frame arrives too slowly, yields `Error::SlowFrame` and then `None`.
+    pub async fn read_raw(&mut self) -> Option<Result<RawPayload>> {
+        if self.inner.get_ref().is_expired() {
+            return None;
+        }
+        let raw = self.inner.next().await?;
+        if self.inner.get_ref().is_expired() {
+            return Some(Err(Error::SlowFrame));
         }
+        let buffered = self.inner.read_buffer().len();
+        self.inner.get_mut().finish_frame(buffered);
+        Some(raw.and_then(|raw| raw).and_then(|raw| {
+            let code = raw.type_code;
+            if !R::Inbound::accepts(code) && R::Outbound::accepts(code) {
+                Err(Error::WrongDirection(code))
+            } else {
+                Ok(raw)
+            }
+        }))
     }
+}
 
-    pub async fn read_raw(&mut self) -> Option<Result<RawPayload>> {
-        if let Some(peeked) = self.peeked.take() {
-            return Some(Ok(peeked));
+/// Hands out frames which fail to decode as items rather than errors.
+///
+/// After an error `FramedRead` ends the stream, and once polled again it waits for more
+/// bytes before decoding those already buffered, so a frame right behind an oversized
+/// one would be stuck. The codec itself recovers from everything but IO errors.
+#[derive(Debug)]
+struct Resuming(Codec);
+
+impl Decoder for Resuming {
+    type Item = Result<RawPayload>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        match self.0.decode(src) {
+            Ok(raw) => Ok(raw.map(Ok)),
+            Err(err @ Error::Io(_)) => Err(err),
+            Err(err) => Ok(Some(Err(err))),
         }
-        self.inner.next().await
     }
 }
 
-pub struct Writer<T>(FramedWrite<T>);
+pub struct Writer<T, R> {
+    inner: FramedWrite<T>,
+    encoding: Encoding,
+    role: PhantomData<R>,
+}
 
-impl<T> Writer<T>
+impl<T, R> Writer<T, R>
 where
     T: AsyncWrite + Unpin,
+    R: Role,
 {
     pub fn new(inner: T) -> Self {
-        Self(new_framed_write(inner))
+        Self {
+            inner: new_framed_write(inner),
+            encoding: Encoding::default(),
+            role: PhantomData,
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Switches the encoding of subsequent payloads, once negotiated.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    /// Compresses large frames, once negotiated.
+    pub fn set_compression(&mut self, compression: Option<Compression>) {
+        self.inner.encoder_mut().set_compression(compression);
+    }
+
+    pub fn set_max_payload_length(&mut self, max_payload_length: usize) {
+        self.inner
+            .encoder_mut()
+            .set_max_payload_length(max_payload_length);
     }
 
     pub async fn write<P>(&mut self, payload: P) -> Result<()>
     where
-        P: SendablePayload,
+        P: SendableBy<R>,
     {
-        let payload = payload.as_raw()?;
-        self.0.send(payload).await
+        let payload = payload.as_raw(self.encoding)?;
+        self.inner.send(payload).await
     }
 }
@@ -0,0 +1,166 @@
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PayloadInfo {
+    pub name: &'static str,
+    pub type_code: u8,
+    pub direction: Direction,
+}
+
+/// Implemented by `#[derive(Payload)]`.
+///
+/// Type codes are unique per direction, the same code may go both ways:
+///
+/// ```
+/// # mod frame {
+/// #     pub use sine_chat::frame::*;
+/// #     pub mod registry {
+/// #         pub use sine_chat::frame::registry::*;
+/// #         pub struct Codes;
+/// #     }
+/// # }
+/// # fn main() {}
+/// use serde::{Deserialize, Serialize};
+/// use sine_chat::frame::Payload;
+///
+/// #[derive(Debug, Serialize, Deserialize, Payload)]
+/// #[payload(code = 0x01, direction = client_to_server)]
+/// struct Request;
+///
+/// #[derive(Debug, Serialize, Deserialize, Payload)]
+/// #[payload(code = 0x01, direction = server_to_client)]
+/// struct Reply;
+/// ```
+///
+/// A second payload with a taken code doesn't compile:
+///
+/// ```compile_fail,E0119
+/// # mod frame {
+/// #     pub use sine_chat::frame::*;
+/// #     pub mod registry {
+/// #         pub use sine_chat::frame::registry::*;
+/// #         pub struct Codes;
+/// #     }
+/// # }
+/// # fn main() {}
+/// use serde::{Deserialize, Serialize};
+/// use sine_chat::frame::Payload;
+///
+/// #[derive(Debug, Serialize, Deserialize, Payload)]
+/// #[payload(code = 0x01, direction = client_to_server)]
+/// struct Request;
+///
+/// #[derive(Debug, Serialize, Deserialize, Payload)]
+/// #[payload(code = 0x01, direction = client_to_server)]
+/// struct Duplicate;
+/// ```
+pub trait Payload {
+    const INFO: PayloadInfo;
+}
+
+// The derive implements `Registered` for (direction, code) on `Codes`, so a duplicate
+// is a conflicting impl, and `payloads` finds every payload by probing all the slots.
+
+/// Every payload of the protocol, in both directions, by type code.
+pub fn payloads() -> Vec<PayloadInfo> {
+    sine_chat_derive::registered_payloads!()
+}
+
+/// Holds the `Registered` impls of this crate's payloads.
+#[doc(hidden)]
+pub struct Codes;
+
+#[doc(hidden)]
+pub trait Registered<const CLIENT_TO_SERVER: bool, const CODE: u8> {
+    const INFO: PayloadInfo;
+}
+
+#[doc(hidden)]
+pub struct Slot<const CLIENT_TO_SERVER: bool, const CODE: u8>;
+
+// Autoref specialization: a slot taken by value has `info` of the registered payload,
+// one with nothing registered only gets the fallback through `&Slot`.
+
+#[doc(hidden)]
+pub trait ViaRegistered {
+    fn info(self) -> Option<PayloadInfo>;
+}
+
+impl<const CLIENT_TO_SERVER: bool, const CODE: u8> ViaRegistered for Slot<CLIENT_TO_SERVER, CODE>
+where
+    Codes: Registered<CLIENT_TO_SERVER, CODE>,
+{
+    fn info(self) -> Option<PayloadInfo> {
+        Some(<Codes as Registered<CLIENT_TO_SERVER, CODE>>::INFO)
+    }
+}
+
+#[doc(hidden)]
+pub trait ViaUnregistered {
+    fn info(self) -> Option<PayloadInfo>;
+}
+
+impl<const CLIENT_TO_SERVER: bool, const CODE: u8> ViaUnregistered
+    for &Slot<CLIENT_TO_SERVER, CODE>
+{
+    fn info(self) -> Option<PayloadInfo> {
+        None
+    }
+}
+
+/// Renders the type code table of the README.
+pub fn protocol_table(payloads: &[PayloadInfo]) -> String {
+    let name_of = |type_code: u8, direction: Direction| {
+        payloads
+            .iter()
+            .find(|p| p.type_code == type_code && p.direction == direction)
+            .map(|p| p.name)
+            .unwrap_or("N/A")
+    };
+    let mut codes: Vec<_> = payloads.iter().map(|p| p.type_code).collect();
+    codes.sort_unstable();
+    codes.dedup();
+
+    let mut table = String::new();
+    table.push_str("| Type Code | From Client | From Server |\n");
+    table.push_str("| :---: | :----: | :---: |\n");
+    for &code in &codes {
+        let _ = writeln!(
+            table,
+            "| 0x{:02X} | {} | {} |",
+            code,
+            name_of(code, Direction::ClientToServer),
+            name_of(code, Direction::ServerToClient)
+        );
+    }
+    for (start, end) in reserved_ranges(&codes) {
+        let range = if start == end {
+            format!("0x{:02X}", start)
+        } else {
+            format!("0x{:02X} ~ 0x{:02X}", start, end)
+        };
+        let _ = writeln!(table, "| {} | [Reserved] | [Reserved] |", range);
+    }
+    table
+}
+
+fn reserved_ranges(sorted_codes: &[u8]) -> Vec<(u8, u8)> {
+    let mut ranges = Vec::new();
+    let mut next_free = 0u16;
+    for &code in sorted_codes {
+        if u16::from(code) > next_free {
+            ranges.push((next_free as u8, code - 1));
+        }
+        next_free = u16::from(code) + 1;
+    }
+    if next_free <= 0xFF {
+        ranges.push((next_free as u8, 0xFF));
+    }
+    ranges
+}
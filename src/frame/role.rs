@@ -0,0 +1,50 @@
+use super::{
+    messages::{self, client},
+    Encoding, ReceivableEnum, ReceivablePayload, SendablePayload,
+};
+
+/// The side of a connection a `Reader` or `Writer` belongs to.
+pub trait Role: Sized + Send + 'static {
+    /// Every payload this side receives.
+    type Inbound: ReceivableEnum + ReceivableBy<Self>;
+    /// Every payload the peer receives.
+    type Outbound: ReceivableEnum;
+}
+
+#[derive(Debug)]
+pub enum ServerSide {}
+
+#[derive(Debug)]
+pub enum ClientSide {}
+
+impl Role for ServerSide {
+    type Inbound = messages::Inbound;
+    type Outbound = client::Inbound;
+}
+
+impl Role for ClientSide {
+    type Inbound = client::Inbound;
+    type Outbound = messages::Inbound;
+}
+
+/// Payloads the role `R` may send, implemented by `#[derive(Payload)]`.
+///
+/// ```compile_fail
+/// # use sine_chat::{frame::{ClientSide, Writer}, message::MessageReply};
+/// # async fn send(writer: &mut Writer<tokio::io::DuplexStream, ClientSide>) {
+/// // `MessageReply` is only sent by servers.
+/// writer.write(MessageReply::success(None)).await;
+/// # }
+/// ```
+pub trait SendableBy<R: Role>: SendablePayload {}
+
+/// Payloads the role `R` may receive, implemented by `#[derive(Payload)]`.
+pub trait ReceivableBy<R: Role>: ReceivablePayload {}
+
+impl<R: Role> SendablePayload for Box<dyn SendableBy<R>> {
+    fn as_raw(&self, encoding: Encoding) -> super::Result<super::RawPayload> {
+        self.as_ref().as_raw(encoding)
+    }
+}
+
+impl<R: Role> SendableBy<R> for Box<dyn SendableBy<R>> {}
@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::info;
+use tokio::{sync::watch, time::Instant};
+
+use super::Config;
+
+/// Number of tracked IPs beyond which those without recent failures are dropped.
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Open connections and failed handshakes, by IP.
+#[derive(Debug)]
+pub struct Admission {
+    config: Arc<Config>,
+    state: Mutex<State>,
+    /// Set once the server shuts down, closing every admitted connection.
+    closing: watch::Sender<bool>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    connections: usize,
+    by_ip: HashMap<IpAddr, usize>,
+    failures: HashMap<IpAddr, Failures>,
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+    banned_until: Option<Instant>,
+}
+
+/// Why a connection was turned away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    TooManyConnections,
+    TooManyConnectionsFromIp,
+    Banned,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            Self::TooManyConnections => "Too many connections",
+            Self::TooManyConnectionsFromIp => "Too many connections from the IP",
+            Self::Banned => "Banned after failed handshakes",
+        };
+        f.write_str(description)
+    }
+}
+
+/// An admitted connection, which frees its slot when dropped.
+#[derive(Debug)]
+pub struct Permit {
+    admission: Arc<Admission>,
+    addr: SocketAddr,
+}
+
+impl Admission {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self {
+            config,
+            state: Default::default(),
+            closing: watch::Sender::new(false),
+        }
+    }
+
+    /// Tells every admitted connection to close.
+    pub fn close_all(&self) {
+        self.closing.send_replace(true);
+    }
+
+    /// Takes a slot for a connection from `addr`, unless it's over a cap or banned.
+    pub fn admit(self: &Arc<Self>, addr: SocketAddr) -> Result<Permit, Rejection> {
+        let ip = addr.ip();
+        let mut state = self.state.lock().unwrap();
+        let banned_until = state.failures.get(&ip).and_then(|f| f.banned_until);
+        if banned_until.is_some_and(|until| until > Instant::now()) {
+            return Err(Rejection::Banned);
+        }
+        if state.connections >= self.config.max_connections {
+            return Err(Rejection::TooManyConnections);
+        }
+        let from_ip = state.by_ip.entry(ip).or_default();
+        if *from_ip >= self.config.max_connections_per_ip {
+            return Err(Rejection::TooManyConnectionsFromIp);
+        }
+        *from_ip += 1;
+        state.connections += 1;
+        let permit = Permit {
+            admission: self.clone(),
+            addr,
+        };
+        Ok(permit)
+    }
+}
+
+impl Admission {
+    fn fail_handshake(&self, addr: SocketAddr) {
+        let now = Instant::now();
+        let ban = self.config.handshake_ban;
+        let mut state = self.state.lock().unwrap();
+        if state.failures.len() > PRUNE_THRESHOLD {
+            state
+                .failures
+                .retain(|_, failures| failures.is_recent(now, ban));
+        }
+        let failures = state.failures.entry(addr.ip()).or_insert(Failures {
+            count: 0,
+            last: now,
+            banned_until: None,
+        });
+        // Failures are forgotten once they are as old as a ban.
+        if !failures.is_recent(now, ban) {
+            failures.count = 0;
+        }
+        failures.count += 1;
+        failures.last = now;
+        if failures.count >= self.config.max_failed_handshakes {
+            info!(
+                "Banning {} for {:?} after {} failed handshakes",
+                addr.ip(),
+                ban,
+                failures.count
+            );
+            failures.count = 0;
+            failures.banned_until = Some(now + ban);
+        }
+    }
+
+    fn release(&self, ip: IpAddr) {
+        let mut state = self.state.lock().unwrap();
+        state.connections -= 1;
+        if let Some(from_ip) = state.by_ip.get_mut(&ip) {
+            *from_ip -= 1;
+            if *from_ip == 0 {
+                state.by_ip.remove(&ip);
+            }
+        }
+    }
+}
+
+impl Failures {
+    fn is_recent(&self, now: Instant, ban: Duration) -> bool {
+        self.banned_until.is_some_and(|until| until > now) || now.duration_since(self.last) < ban
+    }
+}
+
+impl Permit {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Resolves once the server shuts down.
+    pub fn closed(&self) -> impl Future<Output = ()> + 'static {
+        let mut closing = self.admission.closing.subscribe();
+        async move {
+            // The sender lives as long as any permit.
+            let _ = closing.wait_for(|closing| *closing).await;
+        }
+    }
+
+    /// Counts a failed handshake against the IP, which is banned after too many.
+    pub fn fail_handshake(&self) {
+        self.admission.fail_handshake(self.addr);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.admission.release(self.addr.ip());
+    }
+}
@@ -0,0 +1,478 @@
+use std::{
+    collections::HashMap,
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::Mutex,
+};
+
+use crate::message::{ErrorCode, UploadChunk, UploadRequest};
+
+//
+// <dir>/<sha256>                     Completed blob, named by its content
+// <dir>/<sha256>.json                `BlobMeta`
+// <dir>/partial/<transfer_id>        Bytes received so far
+// <dir>/partial/<transfer_id>.json   `PartialMeta`
+//
+
+pub type Result<T> = std::result::Result<T, ErrorCode>;
+
+/// Content-addressed blobs on local disk, along with the uploads still in progress.
+#[derive(Debug)]
+pub struct BlobStore {
+    dir: PathBuf,
+    /// Serializes read-modify-write cycles of `BlobMeta`.
+    meta_lock: Mutex<()>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BlobMeta {
+    pub name: String,
+    pub size: u64,
+    pub mime: String,
+    /// Users allowed to read the blob: uploaders and everyone it was sent to.
+    pub readers: Vec<String>,
+    /// Set once the blob has been validated as an image.
+    #[serde(default)]
+    pub image: Option<ImageMeta>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageMeta {
+    pub width: u32,
+    pub height: u32,
+    pub thumbnails: Vec<ThumbnailMeta>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThumbnailMeta {
+    pub blob_id: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl BlobMeta {
+    pub fn can_read(&self, uid: &str) -> bool {
+        self.readers.iter().any(|reader| reader == uid)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PartialMeta {
+    owner: String,
+    name: String,
+    size: u64,
+    mime: String,
+    checksum: String,
+}
+
+/// Progress of an upload after a request or chunk.
+#[derive(Debug)]
+pub struct Progress {
+    pub transfer_id: String,
+    pub offset: u64,
+    pub blob_id: Option<String>,
+}
+
+impl BlobStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            meta_lock: Mutex::new(()),
+        }
+    }
+
+    /// Starts an upload, or resumes it when the request carries a known transfer id.
+    pub async fn start_upload(&self, owner: &str, request: UploadRequest) -> Result<Progress> {
+        let checksum = request.checksum.to_lowercase();
+        // Only readers skip the upload, knowing a hash mustn't be enough to get the content.
+        if let Ok(meta) = self.meta(owner, &checksum).await {
+            return Ok(Progress {
+                transfer_id: request.transfer_id.unwrap_or_else(generate_id),
+                offset: meta.size,
+                blob_id: Some(checksum),
+            });
+        }
+
+        let transfer_id = match request.transfer_id {
+            Some(transfer_id) => {
+                let meta = self.partial_meta(owner, &transfer_id).await?;
+                if meta.checksum != checksum || meta.size != request.size {
+                    return Err(ErrorCode::ChecksumMismatch);
+                }
+                transfer_id
+            }
+            None => {
+                let transfer_id = generate_id();
+                let meta = PartialMeta {
+                    owner: owner.into(),
+                    name: request.name,
+                    size: request.size,
+                    mime: request.mime,
+                    checksum,
+                };
+                fs::create_dir_all(self.partial_dir())
+                    .await
+                    .map_err(internal)?;
+                write_meta(self.partial_path(&transfer_id, "json"), &meta).await?;
+                File::create(self.partial_path(&transfer_id, ""))
+                    .await
+                    .map_err(internal)?;
+                transfer_id
+            }
+        };
+        let offset = self.received(&transfer_id).await?;
+        self.complete_if_done(transfer_id, offset).await
+    }
+
+    /// Appends a chunk, it must start exactly where the received bytes end.
+    pub async fn write_chunk(&self, owner: &str, chunk: UploadChunk) -> Result<Progress> {
+        let meta = self.partial_meta(owner, &chunk.transfer_id).await?;
+        if crc32fast::hash(&chunk.data) != chunk.checksum {
+            return Err(ErrorCode::ChecksumMismatch);
+        }
+        let offset = self.received(&chunk.transfer_id).await?;
+        let end = chunk.offset + chunk.data.len() as u64;
+        if chunk.offset != offset || end > meta.size {
+            return Err(ErrorCode::OffsetMismatch);
+        }
+
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(self.partial_path(&chunk.transfer_id, ""))
+            .await
+            .map_err(internal)?;
+        file.write_all(&chunk.data).await.map_err(internal)?;
+        file.flush().await.map_err(internal)?;
+
+        self.complete_if_done(chunk.transfer_id, end).await
+    }
+
+    /// Stores a whole blob of `size` bytes read from `content` in one go, returns its id.
+    pub async fn put<R>(
+        &self,
+        owner: &str,
+        name: String,
+        mime: String,
+        size: u64,
+        content: R,
+    ) -> Result<String>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut content = content.take(size);
+        fs::create_dir_all(self.partial_dir())
+            .await
+            .map_err(internal)?;
+        let path = self.partial_path(&generate_id(), "");
+        let mut file = File::create(&path).await.map_err(internal)?;
+        let mut hasher = Sha256::new();
+        let mut received = 0;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let len = match content.read(&mut buf).await {
+                Ok(len) => len,
+                Err(err) => {
+                    let _ = fs::remove_file(&path).await;
+                    return Err(internal(err));
+                }
+            };
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buf[..len]);
+            file.write_all(&buf[..len]).await.map_err(internal)?;
+            received += len as u64;
+        }
+        file.flush().await.map_err(internal)?;
+        if received < size {
+            let _ = fs::remove_file(&path).await;
+            return Err(ErrorCode::MalformedPayload);
+        }
+
+        let blob_id = format!("{:x}", hasher.finalize());
+        let meta = BlobMeta {
+            name,
+            size,
+            mime,
+            readers: vec![owner.into()],
+            image: None,
+        };
+        self.insert(&path, &blob_id, meta).await?;
+        Ok(blob_id)
+    }
+
+    /// Reads at most `len` bytes from `offset`, along with the size of the blob.
+    pub async fn read_chunk(
+        &self,
+        uid: &str,
+        blob_id: &str,
+        offset: u64,
+        len: usize,
+    ) -> Result<(Vec<u8>, u64)> {
+        let (mut file, meta) = self.open(uid, blob_id).await?;
+        if offset > meta.size {
+            return Err(ErrorCode::OffsetMismatch);
+        }
+        file.seek(SeekFrom::Start(offset)).await.map_err(internal)?;
+        let len = len.min((meta.size - offset) as usize);
+        let mut data = vec![0; len];
+        file.read_exact(&mut data).await.map_err(internal)?;
+        Ok((data, meta.size))
+    }
+
+    /// Metadata of a blob `uid` may read, others' blobs are as good as missing.
+    pub async fn meta(&self, uid: &str, blob_id: &str) -> Result<BlobMeta> {
+        if !is_valid_id(blob_id) {
+            return Err(ErrorCode::BlobNotFound);
+        }
+        read_meta::<BlobMeta>(self.blob_path(blob_id, "json"))
+            .await?
+            .filter(|meta| meta.can_read(uid))
+            .ok_or(ErrorCode::BlobNotFound)
+    }
+
+    /// Opens a blob `uid` may read.
+    pub async fn open(&self, uid: &str, blob_id: &str) -> Result<(File, BlobMeta)> {
+        let meta = self.meta(uid, blob_id).await?;
+        let file = File::open(self.blob_path(blob_id, ""))
+            .await
+            .map_err(internal)?;
+        Ok((file, meta))
+    }
+
+    /// Lets `grantee` read a blob `granter` may read.
+    pub async fn grant(&self, granter: &str, blob_id: &str, grantee: &str) -> Result<()> {
+        self.meta(granter, blob_id).await?;
+        self.allow(blob_id, grantee).await
+    }
+
+    /// Lets `uid` read a blob, without checking anyone's access.
+    pub async fn allow(&self, blob_id: &str, uid: &str) -> Result<()> {
+        self.update(blob_id, |meta| {
+            if !meta.can_read(uid) {
+                meta.readers.push(uid.into());
+            }
+        })
+        .await
+    }
+
+    /// Records what validating the blob as an image found out.
+    pub async fn set_image(&self, blob_id: &str, image: ImageMeta) -> Result<()> {
+        self.update(blob_id, |meta| meta.image = Some(image)).await
+    }
+
+    /// Removes unfinished uploads which haven't received a byte for `idle`.
+    pub async fn remove_idle_uploads(&self, idle: Duration) -> Result<()> {
+        let mut entries = match fs::read_dir(self.partial_dir()).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(internal(err)),
+        };
+        // An upload is its content and its `PartialMeta`, last touched by whichever is newer.
+        let mut uploads = HashMap::<PathBuf, SystemTime>::new();
+        while let Some(entry) = entries.next_entry().await.map_err(internal)? {
+            let path = entry.path().with_extension("");
+            let modified = entry
+                .metadata()
+                .await
+                .and_then(|metadata| metadata.modified())
+                .map_err(internal)?;
+            let last = uploads.entry(path).or_insert(modified);
+            *last = (*last).max(modified);
+        }
+        let now = SystemTime::now();
+        for (path, last) in uploads {
+            if now.duration_since(last).unwrap_or_default() < idle {
+                continue;
+            }
+            let _ = fs::remove_file(&path).await;
+            let _ = fs::remove_file(path.with_extension("json")).await;
+        }
+        Ok(())
+    }
+
+    /// Path of a blob's content, only to be used once access has been checked.
+    pub fn content_path(&self, blob_id: &str) -> PathBuf {
+        self.blob_path(blob_id, "")
+    }
+}
+
+impl BlobStore {
+    async fn update<F>(&self, blob_id: &str, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut BlobMeta),
+    {
+        let _lock = self.meta_lock.lock().await;
+        let path = self.blob_path(blob_id, "json");
+        let mut meta = read_meta::<BlobMeta>(path.clone())
+            .await?
+            .ok_or(ErrorCode::BlobNotFound)?;
+        f(&mut meta);
+        write_meta(path, &meta).await
+    }
+
+    async fn partial_meta(&self, owner: &str, transfer_id: &str) -> Result<PartialMeta> {
+        if !is_valid_id(transfer_id) {
+            return Err(ErrorCode::TransferNotFound);
+        }
+        read_meta::<PartialMeta>(self.partial_path(transfer_id, "json"))
+            .await?
+            // Others' transfers are as good as missing.
+            .filter(|meta| meta.owner == owner)
+            .ok_or(ErrorCode::TransferNotFound)
+    }
+
+    async fn received(&self, transfer_id: &str) -> Result<u64> {
+        let metadata = fs::metadata(self.partial_path(transfer_id, ""))
+            .await
+            .map_err(internal)?;
+        Ok(metadata.len())
+    }
+
+    /// Verifies and moves a fully received upload into place.
+    async fn complete_if_done(&self, transfer_id: String, offset: u64) -> Result<Progress> {
+        let meta_path = self.partial_path(&transfer_id, "json");
+        let meta = read_meta::<PartialMeta>(meta_path.clone())
+            .await?
+            .ok_or(ErrorCode::TransferNotFound)?;
+        if offset < meta.size {
+            return Ok(Progress {
+                transfer_id,
+                offset,
+                blob_id: None,
+            });
+        }
+
+        let path = self.partial_path(&transfer_id, "");
+        if sha256(&path).await.map_err(internal)? != meta.checksum {
+            // The content is unusable, the client has to start over.
+            let _ = fs::remove_file(&path).await;
+            let _ = fs::remove_file(&meta_path).await;
+            return Err(ErrorCode::ChecksumMismatch);
+        }
+
+        let PartialMeta {
+            owner,
+            name,
+            size,
+            mime,
+            checksum: blob_id,
+        } = meta;
+        let blob = BlobMeta {
+            name,
+            size,
+            mime,
+            readers: vec![owner],
+            image: None,
+        };
+        self.insert(&path, &blob_id, blob).await?;
+        fs::remove_file(&meta_path).await.map_err(internal)?;
+
+        Ok(Progress {
+            transfer_id,
+            offset,
+            blob_id: Some(blob_id),
+        })
+    }
+
+    /// Moves verified content at `path` into place, or drops it when the blob is already stored.
+    async fn insert(&self, path: &Path, blob_id: &str, meta: BlobMeta) -> Result<()> {
+        let _lock = self.meta_lock.lock().await;
+        let meta_path = self.blob_path(blob_id, "json");
+        match read_meta::<BlobMeta>(meta_path.clone()).await? {
+            Some(mut stored) => {
+                fs::remove_file(path).await.map_err(internal)?;
+                for reader in meta.readers {
+                    if !stored.can_read(&reader) {
+                        stored.readers.push(reader);
+                    }
+                }
+                write_meta(meta_path, &stored).await
+            }
+            None => {
+                fs::rename(path, self.blob_path(blob_id, ""))
+                    .await
+                    .map_err(internal)?;
+                write_meta(meta_path, &meta).await
+            }
+        }
+    }
+
+    fn partial_dir(&self) -> PathBuf {
+        self.dir.join("partial")
+    }
+
+    fn partial_path(&self, transfer_id: &str, extension: &str) -> PathBuf {
+        self.partial_dir()
+            .join(transfer_id)
+            .with_extension(extension)
+    }
+
+    fn blob_path(&self, blob_id: &str, extension: &str) -> PathBuf {
+        self.dir.join(blob_id).with_extension(extension)
+    }
+}
+
+// Helpers
+
+/// Ids end up in paths, so only ever accept hex: SHA-256 digests and `generate_id`'s output.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn generate_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}{:08x}{:08x}", nanos, std::process::id(), count)
+}
+
+async fn sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let len = file.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buf[..len]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+async fn read_meta<T: DeserializeOwned>(path: PathBuf) -> Result<Option<T>> {
+    match fs::read(path).await {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|err| internal(err.into())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(internal(err)),
+    }
+}
+
+async fn write_meta<T: Serialize>(path: PathBuf, meta: &T) -> Result<()> {
+    let bytes = serde_json::to_vec(meta).map_err(|err| internal(err.into()))?;
+    // Written aside first, so readers and crashes never see it half written.
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, bytes).await.map_err(internal)?;
+    fs::rename(&temp, path).await.map_err(internal)
+}
+
+fn internal(err: io::Error) -> ErrorCode {
+    error!("Blob store error: {}", err);
+    ErrorCode::Internal
+}
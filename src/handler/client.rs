@@ -1,6 +1,10 @@
 use log::error;
+use tokio::sync::mpsc;
 
-use crate::frame::SendablePayload;
+use crate::{
+    frame::{SendableBy, ServerSide},
+    message::Capability,
+};
 
 use super::Sender;
 
@@ -8,19 +12,73 @@ use super::Sender;
 pub struct Client {
     pub uid: String,
     sender: Sender,
+    /// Capabilities negotiated in the handshake.
+    capabilities: Vec<Capability>,
+    /// Secret part of the session's token for the HTTP endpoint.
+    http_secret: String,
 }
 
 impl Client {
-    pub(crate) fn new(uid: String, sender: Sender) -> Self {
-        Self { uid, sender }
+    pub(crate) fn new(uid: String, sender: Sender, capabilities: Vec<Capability>) -> Self {
+        Self {
+            uid,
+            sender,
+            capabilities,
+            http_secret: random_hex(16),
+        }
+    }
+
+    /// Stands in for a user who isn't connected, sending to it goes nowhere.
+    pub(crate) fn offline(uid: String) -> Self {
+        let (sender, _) = mpsc::channel(1);
+        Self::new(uid, sender, Vec::new())
+    }
+
+    /// Bearer token of the HTTP endpoint, valid as long as the session.
+    pub fn http_token(&self) -> String {
+        format!("{}.{}", self.uid, self.http_secret)
+    }
+
+    pub fn accepts_http_secret(&self, secret: &str) -> bool {
+        let (expected, secret) = (self.http_secret.as_bytes(), secret.as_bytes());
+        // Compares every byte, so the time taken doesn't tell how much of a guess matched.
+        let diff = expected
+            .iter()
+            .zip(secret)
+            .fold(0, |diff, (a, b)| diff | (a ^ b));
+        expected.len() == secret.len() && diff == 0
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
     }
 
     pub async fn send<T>(&self, payload: T)
     where
-        T: SendablePayload + 'static,
+        T: SendableBy<ServerSide> + 'static,
     {
+        if self.sender.is_closed() {
+            // Gone already, nobody to tell.
+            return;
+        }
         if let Err(err) = self.sender.send(Box::new(payload)).await {
             error!("Client sending error: {}", err);
         }
     }
+
+    /// Sends an event of `capability`, unless the client didn't negotiate it.
+    pub async fn notify<T>(&self, capability: Capability, event: T)
+    where
+        T: SendableBy<ServerSide> + 'static,
+    {
+        if self.supports(capability) {
+            self.send(event).await
+        }
+    }
+}
+
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0; len];
+    getrandom::fill(&mut bytes).expect("No randomness available");
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
 }
@@ -2,118 +2,294 @@ use std::{sync::Arc, time::Duration};
 
 use guard::guard;
 use log::{error, info};
-use tokio::{net::TcpStream, select, sync::mpsc, task::JoinHandle, time};
+use tokio::{
+    net::TcpStream,
+    select,
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+    time,
+};
 
 use crate::{
-    frame,
-    message::{ClientMessage, Handshake, HandshakeReply, Ping},
+    frame::{self, messages::Inbound, Compression, Encoding, ReceiveLimits},
+    message::{
+        Capability, ErrorCode, Handshake, HandshakeReply, Limits, MessageReply, RateLimited,
+        MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    },
 };
 
-use super::{Client, Clients, Entry, Item, Reader, Receiver, Sender, Writer};
+use super::{
+    check_content,
+    rate_limit::{RateLimiter, TokenBucket},
+    transfer, BlobStore, Client, Clients, Config, Entry, Item, Permit, Reader, Receiver, Sender,
+    Writer,
+};
 
-const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Longest time spent writing out what's queued for a client being disconnected.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 pub struct ClientTask {
     clients: Clients,
+    blobs: Arc<BlobStore>,
+    limiter: Arc<RateLimiter>,
+    config: Arc<Config>,
+    permit: Permit,
     sender: Sender,
     client: Option<Arc<Client>>,
     sending_task: Option<JoinHandle<()>>,
+    /// Makes the sending task stop once it has written what's queued.
+    stop_sending: Option<oneshot::Sender<()>>,
 }
 
 impl ClientTask {
-    pub async fn run(stream: TcpStream, entry: Entry, clients: Clients) {
+    pub async fn run(
+        stream: TcpStream,
+        permit: Permit,
+        entry: Entry,
+        clients: Clients,
+        blobs: Arc<BlobStore>,
+        limiter: Arc<RateLimiter>,
+        config: Arc<Config>,
+    ) {
         let (sender, receiver) = mpsc::channel(256);
         let mut task = ClientTask {
             clients,
+            blobs,
+            limiter,
+            config,
+            permit,
             sender,
             client: None,
             sending_task: None,
+            stop_sending: None,
         };
-        let (mut reader, mut writer) = Self::split(stream);
+        let closed = task.permit.closed();
+        select! {
+            _ = closed => info!("Closing connection from {}", task.permit.addr()),
+            _ = task.serve(stream, receiver, entry) => (),
+        }
+    }
+
+    async fn serve(&mut self, stream: TcpStream, receiver: Receiver, entry: Entry) {
+        let (mut reader, mut writer) = self.split(stream);
         // Step 1: handshake
-        if !task.handshake(&mut reader, &mut writer).await {
+        if let Err(code) = self.handshake(&mut reader, &mut writer).await {
+            info!("Handshake failed from {}", self.permit.addr());
+            if code.is_some_and(is_misbehaving) {
+                self.permit.fail_handshake();
+            }
             return;
         }
         // Step 2: run loop
-        task.run_sending(receiver, writer);
-        task.run_receiving(reader, entry).await;
+        self.run_sending(receiver, writer);
+        self.run_receiving(reader, entry).await;
     }
 }
 
 impl ClientTask {
-    fn split(stream: TcpStream) -> (Reader, Writer) {
+    fn split(&self, stream: TcpStream) -> (Reader, Writer) {
         let (reader, writer) = stream.into_split();
-        (Reader::new(reader), Writer::new(writer))
+        let (mut reader, mut writer) = (Reader::new(reader), Writer::new(writer));
+        reader.set_max_payload_length(self.config.max_payload_length);
+        writer.set_max_payload_length(self.config.max_payload_length);
+        reader.set_receive_limits(ReceiveLimits {
+            timeout: self.config.frame_timeout,
+            min_throughput: self.config.min_frame_throughput,
+        });
+        (reader, writer)
     }
 }
 
 // Handshake
 
 impl ClientTask {
-    async fn handshake(&mut self, reader: &mut Reader, writer: &mut Writer) -> bool {
+    /// Fails with the code replied to the client, `None` if no handshake arrived.
+    #[allow(clippy::diverging_sub_expression)]
+    async fn handshake(
+        &mut self,
+        reader: &mut Reader,
+        writer: &mut Writer,
+    ) -> Result<(), Option<ErrorCode>> {
         let handshake = select! {
-            _ = time::sleep(HANDSHAKE_TIMEOUT) => None,
+            _ = time::sleep(self.config.handshake_timeout) => None,
             handshake = reader.read::<Handshake>() => handshake,
         };
-        guard!(let Some(handshake) = handshake else { return false });
+        guard!(let Some(handshake) = handshake else { return Err(None) });
 
         let (success, reply) = self.process_handshake(handshake);
+        let (encoding, compression, code) = (reply.encoding, reply.compression, reply.code);
         if let Err(err) = writer.write(reply).await {
             error!("Writer error: {}", err);
         }
         if success {
+            // The reply is still in the initial encoding, everything after it uses the negotiated one.
+            reader.set_encoding(encoding);
+            writer.set_encoding(encoding);
+            reader.set_compression(compression);
+            writer.set_compression(compression);
             info!("Client connected: {}", self.client.as_ref().unwrap().uid);
+            Ok(())
+        } else {
+            Err(code)
         }
-        success
     }
 
     fn process_handshake(&mut self, handshake: frame::Result<Handshake>) -> (bool, HandshakeReply) {
         let mut clients = self.clients.lock().unwrap();
 
-        let (token, reply) = match handshake {
-            Ok(handshake) => {
-                if clients.contains_key(&handshake.token) {
+        let (token, mut reply) = match handshake {
+            Ok(handshake) => match handshake.negotiate_version() {
+                None => {
+                    let message = format!(
+                        "Supported versions: {} ~ {}",
+                        MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+                    );
                     (
                         None,
-                        HandshakeReply::failed(Some("User existed".to_string())),
+                        HandshakeReply::failed(ErrorCode::UnsupportedVersion, Some(message)),
                     )
-                } else {
-                    (Some(handshake.token), HandshakeReply::success(None))
                 }
-            }
+                Some(_) if clients.contains_key(&handshake.token) => {
+                    (None, HandshakeReply::failed(ErrorCode::UserExists, None))
+                }
+                Some(version) => {
+                    let reply = self.accept_handshake(&handshake, version);
+                    let capabilities = reply.capabilities.clone();
+                    (Some((handshake.token, capabilities)), reply)
+                }
+            },
             Err(err) => (None, HandshakeReply::error(err)),
         };
 
-        self.client = token.map(|uid| {
-            let client = Arc::new(Client::new(uid.clone(), self.sender.clone()));
+        self.client = token.map(|(uid, capabilities)| {
+            let client = Arc::new(Client::new(uid.clone(), self.sender.clone(), capabilities));
             clients.insert(uid, client.clone());
+            reply.http_token = Some(client.http_token());
             client
         });
 
         let success = self.client.is_some();
         (success, reply)
     }
+
+    fn accept_handshake(&self, handshake: &Handshake, version: u32) -> HandshakeReply {
+        if let Some(client) = &handshake.client {
+            info!(
+                "Client {} uses {} {} (v{})",
+                handshake.token, client.name, client.version, handshake.version
+            );
+        }
+        let mut reply = HandshakeReply::success(None);
+        reply.version = version;
+        reply.capabilities = Capability::negotiate(&handshake.capabilities);
+        reply.limits = Some(Limits {
+            max_payload_length: self.config.max_payload_length,
+            rate_limits: self.config.user_rate_limits.announced(),
+        });
+        reply.encoding = Encoding::negotiate(&handshake.encodings);
+        reply.compression = Compression::negotiate(&handshake.compressions);
+        reply
+    }
+}
+
+/// Whether a handshake failing with `code` counts toward a ban: protocol violations
+/// do, a taken name or a connection dropping midway don't.
+fn is_misbehaving(code: ErrorCode) -> bool {
+    matches!(
+        code,
+        ErrorCode::UnsupportedVersion
+            | ErrorCode::MalformedPayload
+            | ErrorCode::TypeMismatch
+            | ErrorCode::TooLarge
+    )
 }
 
 // Sending & Receiving
 
 impl ClientTask {
+    #[allow(clippy::diverging_sub_expression)]
     fn run_sending(&mut self, mut receiver: Receiver, mut writer: Writer) {
+        let (stop, mut stopped) = oneshot::channel();
         let task = tokio::spawn(async move {
+            let mut stopping = false;
             loop {
-                guard!(let Some(msg) = receiver.recv().await else { break });
+                let msg = select! {
+                    msg = receiver.recv() => msg,
+                    _ = &mut stopped, if !stopping => {
+                        // Nothing more is queued, what's there still goes out.
+                        stopping = true;
+                        receiver.close();
+                        continue;
+                    }
+                };
+                guard!(let Some(msg) = msg else { break });
                 if let Err(err) = writer.write(msg).await {
                     error!("Writer error: {}", err);
                 }
             }
         });
         self.sending_task = Some(task);
+        self.stop_sending = Some(stop);
+    }
+
+    /// Writes out what's queued for the client and stops sending.
+    async fn flush(&mut self) {
+        let (Some(stop), Some(task)) = (self.stop_sending.take(), self.sending_task.as_mut())
+        else {
+            return;
+        };
+        let _ = stop.send(());
+        if time::timeout(FLUSH_TIMEOUT, task).await.is_err() {
+            info!("Gave up flushing to {}", self.permit.addr());
+        }
     }
 
-    async fn run_receiving(&self, mut reader: Reader, entry: Entry) {
+    #[allow(clippy::diverging_sub_expression)]
+    async fn run_receiving(&mut self, mut reader: Reader, entry: Entry) {
         guard!(let Some(client) = self.client.clone() else { return });
-        while let Some(msg) = reader.read_either::<ClientMessage, Ping>().await {
+        let mut violations = TokenBucket::new(self.config.rate_violations);
+        while let Some(msg) = reader.read_any().await {
+            if let Err(err @ frame::Error::SlowFrame) = msg {
+                info!(
+                    "Disconnecting {} ({}): {}",
+                    client.uid,
+                    self.permit.addr(),
+                    err
+                );
+                client.send(MessageReply::error(err)).await;
+                self.flush().await;
+                break;
+            }
+            // Floods stop here, before reaching the disk or the shared loop.
+            let type_code = msg.as_ref().ok().map(Inbound::type_code);
+            let ip = self.permit.addr().ip();
+            if let Err(retry_after) = self.limiter.check(&client.uid, ip, type_code) {
+                if violations.take(self.config.rate_violations).is_err() {
+                    info!("Disconnecting {} ({}) for flooding", client.uid, ip);
+                    break;
+                }
+                let retry_after = u64::try_from(retry_after.as_millis()).unwrap_or(u64::MAX);
+                client
+                    .send(RateLimited {
+                        type_code,
+                        retry_after,
+                    })
+                    .await;
+                continue;
+            }
+            if let Ok(Err(reply)) = msg.as_ref().map(check_content) {
+                client.send(reply).await;
+                continue;
+            }
+            // Transfers wait on the disk, so they stay on this connection's task.
+            let msg = match msg {
+                Ok(msg) => transfer::handle(msg, &client, &self.blobs, &self.config)
+                    .await
+                    .map(Ok),
+                Err(err) => Some(Err(err)),
+            };
+            guard!(let Some(msg) = msg else { continue });
             let item = Item::new(client.clone(), msg);
             entry.send(item).await.unwrap();
         }
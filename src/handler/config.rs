@@ -0,0 +1,189 @@
+use std::{collections::HashMap, net::SocketAddr, path::PathBuf, time::Duration};
+
+use crate::{
+    frame::{ReceivableSerdePayload, DEFAULT_MAX_PAYLOAD_LENGTH},
+    message::{ClientMessage, DownloadRequest, Ping, RateLimit, UploadChunk},
+};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// How long a connection may stay open without completing the handshake.
+    pub handshake_timeout: Duration,
+    /// Max payload length of a frame in either direction, announced in the handshake.
+    pub max_payload_length: usize,
+    /// Longest time from the first byte of an inbound frame to its last.
+    pub frame_timeout: Duration,
+    /// Average bytes per second an inbound frame has to arrive at after its first second.
+    pub min_frame_throughput: u64,
+    /// Directory of uploaded blobs and unfinished uploads.
+    pub blob_dir: PathBuf,
+    /// Max size of a single blob.
+    pub max_blob_size: u64,
+    /// How long an unfinished upload is kept without receiving a byte.
+    pub upload_idle_timeout: Duration,
+    /// Address of the HTTP endpoint serving blobs, `None` disables it.
+    pub http_addr: Option<SocketAddr>,
+    /// Base URL of the HTTP endpoint as clients reach it, defaults to `http://<http_addr>`.
+    pub public_url: Option<String>,
+    /// Max width and height of images stored on the server.
+    pub max_image_dimension: u32,
+    /// Bounding boxes of the thumbnails generated for images stored on the server.
+    pub thumbnail_sizes: Vec<u32>,
+    /// Log the message history is persisted to, `None` keeps it in memory only.
+    pub history_path: Option<PathBuf>,
+    /// Longest time content of expired or recalled messages stays in the history log.
+    pub history_scrub_delay: Duration,
+    /// How long after sending a message its sender may still delete it.
+    pub recall_window: Duration,
+    /// File pending scheduled messages are saved to, `None` keeps them in memory only.
+    pub schedule_path: Option<PathBuf>,
+    /// How long after finding its receiver offline a scheduled message is tried again.
+    pub schedule_retry: Duration,
+    /// Min interval between `Typing` frames forwarded from a sender to a receiver.
+    pub typing_throttle: Duration,
+    /// How long a sender counts as typing without a refreshing `Typing` frame.
+    pub typing_timeout: Duration,
+    /// Frames each user may send.
+    pub user_rate_limits: RateLimits,
+    /// Frames all connections from an IP may send together.
+    pub ip_rate_limits: RateLimits,
+    /// Over-limit frames a connection may send before it's closed.
+    pub rate_violations: Rate,
+    /// Connections open at once, including those still in the handshake.
+    pub max_connections: usize,
+    /// Connections open at once from a single IP.
+    pub max_connections_per_ip: usize,
+    /// Failed handshakes from an IP, each within `handshake_ban` of the last, before it's banned.
+    pub max_failed_handshakes: u32,
+    /// How long an IP with too many failed handshakes is turned away.
+    pub handshake_ban: Duration,
+}
+
+/// Parameters of a token bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    /// Tokens in a full bucket.
+    pub burst: u32,
+    /// Tokens added back each second.
+    pub per_second: f64,
+}
+
+impl Rate {
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        Self { burst, per_second }
+    }
+}
+
+/// Rates of inbound frames by type code.
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    /// Applies to types without a rate of their own, and to undecodable frames.
+    pub default: Rate,
+    pub by_type: HashMap<u8, Rate>,
+}
+
+impl RateLimits {
+    pub fn rate(&self, type_code: Option<u8>) -> Rate {
+        type_code
+            .and_then(|type_code| self.by_type.get(&type_code))
+            .copied()
+            .unwrap_or(self.default)
+    }
+
+    /// Limits which never kick in, e.g. for benchmarks from a single host.
+    pub fn unlimited() -> Self {
+        Self {
+            default: Rate::new(u32::MAX, f64::MAX),
+            by_type: HashMap::new(),
+        }
+    }
+
+    /// The limits as announced in the handshake, the default first, then by type code.
+    pub fn announced(&self) -> Vec<RateLimit> {
+        let mut by_type: Vec<_> = self.by_type.iter().collect();
+        by_type.sort_by_key(|(type_code, _)| **type_code);
+        let limit = |type_code, rate: &Rate| RateLimit {
+            type_code,
+            burst: rate.burst,
+            per_second: rate.per_second,
+        };
+        std::iter::once(limit(None, &self.default))
+            .chain(by_type.into_iter().map(|(t, rate)| limit(Some(*t), rate)))
+            .collect()
+    }
+
+    /// The same limits with every rate multiplied by `factor`.
+    pub fn scaled(&self, factor: f64) -> Self {
+        let scale = |rate: &Rate| {
+            Rate::new(
+                (rate.burst as f64 * factor) as u32,
+                rate.per_second * factor,
+            )
+        };
+        Self {
+            default: scale(&self.default),
+            by_type: self
+                .by_type
+                .iter()
+                .map(|(type_code, rate)| (*type_code, scale(rate)))
+                .collect(),
+        }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        let by_type = [
+            (ClientMessage::type_code(), Rate::new(20, 5.0)),
+            (Ping::type_code(), Rate::new(5, 1.0)),
+            // Transfers go a chunk per frame.
+            (UploadChunk::type_code(), Rate::new(200, 100.0)),
+            (DownloadRequest::type_code(), Rate::new(200, 100.0)),
+        ];
+        Self {
+            default: Rate::new(50, 20.0),
+            by_type: by_type.into_iter().collect(),
+        }
+    }
+}
+
+impl Config {
+    /// URL of a blob on the HTTP endpoint.
+    pub fn blob_url(&self, blob_id: &str) -> Option<String> {
+        let base = self.public_url.as_ref()?;
+        Some(format!("{}/blobs/{}", base, blob_id))
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            handshake_timeout: Duration::from_secs(5),
+            max_payload_length: DEFAULT_MAX_PAYLOAD_LENGTH,
+            frame_timeout: Duration::from_secs(60),
+            min_frame_throughput: 1024,
+            blob_dir: PathBuf::from("blobs"),
+            max_blob_size: 100 * 1024 * 1024,
+            upload_idle_timeout: Duration::from_secs(24 * 60 * 60),
+            http_addr: None,
+            public_url: None,
+            max_image_dimension: 8192,
+            thumbnail_sizes: vec![128, 512],
+            history_path: None,
+            history_scrub_delay: Duration::from_secs(60),
+            recall_window: Duration::from_secs(2 * 60),
+            schedule_path: None,
+            schedule_retry: Duration::from_secs(30),
+            typing_throttle: Duration::from_secs(3),
+            typing_timeout: Duration::from_secs(10),
+            user_rate_limits: RateLimits::default(),
+            // Leaves room for a few users behind the same NAT.
+            ip_rate_limits: RateLimits::default().scaled(4.0),
+            rate_violations: Rate::new(20, 1.0),
+            max_connections: 10_000,
+            max_connections_per_ip: 100,
+            max_failed_handshakes: 10,
+            handshake_ban: Duration::from_secs(10 * 60),
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use guard::guard;
+use log::error;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use crate::message::{now_millis, ServerMessage};
+
+/// Every delivered message in its latest state, keyed by id.
+///
+/// With a path, changes are appended to a log of JSON lines which is replayed on load.
+/// The log is rewritten from the current state once most of its records are stale, and
+/// within `scrub_delay` of messages expiring or being recalled, so nothing of their
+/// content, edited versions included, stays on disk for longer.
+#[derive(Debug, Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    scrub_delay: Duration,
+    /// Records in the log.
+    records: usize,
+    /// When content of removed messages has to be gone from the log by.
+    scrub_at: Option<u64>,
+    messages: BTreeMap<u64, ServerMessage>,
+    /// Ids of the messages, keyed by sender then receiver.
+    sent: HashMap<(String, String), BTreeSet<u64>>,
+    last_id: u64,
+    /// Messages with a TTL as (expires_at, id).
+    expiring: BTreeSet<(u64, u64)>,
+    /// Id of the last message read, keyed by reader then peer.
+    read: HashMap<(String, String), u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Put {
+        message: ServerMessage,
+    },
+    Delete {
+        id: u64,
+    },
+    Read {
+        reader: String,
+        peer: String,
+        up_to: u64,
+    },
+    /// Keeps ids from being reused once the latest messages are gone.
+    LastId {
+        id: u64,
+    },
+}
+
+/// Records in a log below which it's never compacted for staleness alone.
+const MIN_COMPACTED_RECORDS: usize = 1024;
+
+impl History {
+    pub async fn load(path: Option<PathBuf>, scrub_delay: Duration) -> Self {
+        let mut history = Self {
+            path,
+            scrub_delay,
+            ..Default::default()
+        };
+        let log = match &history.path {
+            Some(path) => fs::read_to_string(path).await.unwrap_or_default(),
+            None => String::new(),
+        };
+        for line in log.lines().filter(|line| !line.is_empty()) {
+            history.records += 1;
+            match serde_json::from_str(line) {
+                Ok(record) => history.apply(record),
+                // A torn last line after a crash loses that change only.
+                Err(err) => error!("Skipping history record: {}", err),
+            }
+        }
+        // Whatever a crash kept from being scrubbed goes now.
+        if history.records > history.live_records() {
+            history.compact().await;
+        }
+        history
+    }
+
+    /// Assigns the message an id and stores it.
+    pub async fn insert(&mut self, mut message: ServerMessage) -> ServerMessage {
+        message.id = self.last_id + 1;
+        self.put(message.clone()).await;
+        message
+    }
+
+    pub fn get(&self, id: u64) -> Option<&ServerMessage> {
+        self.messages.get(&id)
+    }
+
+    /// Stores the latest state of a message.
+    pub async fn put(&mut self, message: ServerMessage) {
+        self.append(Record::Put { message }).await
+    }
+
+    /// Removes a recalled message, every version of it on disk goes by the next scrub.
+    pub async fn remove(&mut self, id: u64) {
+        self.append(Record::Delete { id }).await;
+        self.schedule_scrub();
+    }
+
+    /// When the next message expires, in milliseconds since the Unix epoch.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.expiring
+            .iter()
+            .next()
+            .map(|(expires_at, _)| *expires_at)
+    }
+
+    /// Removes the messages expired by `now`, returns them.
+    ///
+    /// Their content on disk goes by the next scrub.
+    pub async fn expire(&mut self, now: u64) -> Vec<ServerMessage> {
+        let due: Vec<_> = self
+            .expiring
+            .iter()
+            .take_while(|(expires_at, _)| *expires_at <= now)
+            .map(|(_, id)| *id)
+            .collect();
+        let mut expired = Vec::with_capacity(due.len());
+        for id in due {
+            if let Some(message) = self.messages.get(&id).cloned() {
+                self.append(Record::Delete { id }).await;
+                expired.push(message);
+            }
+        }
+        if !expired.is_empty() {
+            self.schedule_scrub();
+        }
+        expired
+    }
+
+    /// When content of removed messages has to be scrubbed from disk by, in milliseconds
+    /// since the Unix epoch.
+    pub fn next_scrub(&self) -> Option<u64> {
+        self.scrub_at
+    }
+
+    /// Rewrites the log without the content of removed messages.
+    pub async fn scrub(&mut self) {
+        self.compact().await
+    }
+
+    /// Moves the read position of `reader` in the conversation with `peer` forward,
+    /// `false` if it's already at or past `up_to`.
+    pub async fn mark_read(&mut self, reader: &str, peer: &str, up_to: u64) -> bool {
+        if self.read_up_to(reader, peer) >= up_to {
+            return false;
+        }
+        let record = Record::Read {
+            reader: reader.into(),
+            peer: peer.into(),
+            up_to,
+        };
+        self.append(record).await;
+        true
+    }
+
+    /// Id of the last message from `peer` that `reader` has read, 0 for none.
+    pub fn read_up_to(&self, reader: &str, peer: &str) -> u64 {
+        let key = (reader.to_string(), peer.to_string());
+        self.read.get(&key).copied().unwrap_or_default()
+    }
+
+    /// Messages from `peer` that `reader` hasn't read yet.
+    pub fn unread(&self, reader: &str, peer: &str) -> usize {
+        self.unread_messages(reader, peer).count()
+    }
+
+    /// Unread messages from `peer` which mention `reader`.
+    pub fn unread_mentions(&self, reader: &str, peer: &str) -> usize {
+        self.unread_messages(reader, peer)
+            .filter(|message| message.content.mentions(reader))
+            .count()
+    }
+
+    /// Messages between `a` and `b` with ids below `before`, the last `limit` of them, oldest first.
+    pub fn conversation(
+        &self,
+        a: &str,
+        b: &str,
+        before: Option<u64>,
+        limit: usize,
+    ) -> Vec<ServerMessage> {
+        self.latest(a, b, 0, before, limit, |_| true)
+    }
+
+    /// Replies to `root`, directly or to other replies, paged like `conversation`.
+    pub fn thread(&self, root: u64, before: Option<u64>, limit: usize) -> Vec<ServerMessage> {
+        let Some(message) = self.messages.get(&root) else {
+            return Vec::new();
+        };
+        // Replies stay within the conversation of what they reply to.
+        let (a, b) = (&message.sender, &message.receiver);
+        self.latest(a, b, root + 1, before, limit, |message| {
+            self.is_in_thread(message, root)
+        })
+    }
+}
+
+impl History {
+    /// The last `limit` messages between `a` and `b` matching `filter`, with ids from
+    /// `after` to below `before`, oldest first.
+    fn latest<F>(
+        &self,
+        a: &str,
+        b: &str,
+        after: u64,
+        before: Option<u64>,
+        limit: usize,
+        filter: F,
+    ) -> Vec<ServerMessage>
+    where
+        F: Fn(&ServerMessage) -> bool,
+    {
+        let before = before.unwrap_or(u64::MAX);
+        if after >= before {
+            return Vec::new();
+        }
+        // The last `limit` of each direction hold the last `limit` of both.
+        let mut ids: Vec<_> = [(a, b), (b, a)]
+            .into_iter()
+            .filter_map(|(sender, receiver)| self.sent_ids(sender, receiver))
+            .flat_map(|ids| {
+                ids.range(after..before)
+                    .rev()
+                    .filter(|id| filter(&self.messages[id]))
+                    .take(limit)
+            })
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        // Both directions are the same with oneself.
+        ids.dedup();
+        let older = ids.len().saturating_sub(limit);
+        ids[older..]
+            .iter()
+            .map(|id| self.messages[id].clone())
+            .collect()
+    }
+
+    fn sent_ids(&self, sender: &str, receiver: &str) -> Option<&BTreeSet<u64>> {
+        self.sent.get(&(sender.to_string(), receiver.to_string()))
+    }
+
+    fn unread_messages<'a>(
+        &'a self,
+        reader: &'a str,
+        peer: &'a str,
+    ) -> impl Iterator<Item = &'a ServerMessage> {
+        let read_up_to = self.read_up_to(reader, peer);
+        self.sent_ids(peer, reader)
+            .into_iter()
+            .flat_map(move |ids| ids.range(read_up_to.saturating_add(1)..))
+            .map(|id| &self.messages[id])
+    }
+
+    fn is_in_thread(&self, message: &ServerMessage, root: u64) -> bool {
+        let mut parent = message.reply_to;
+        // Replies always come after what they reply to, so the walk ends.
+        while let Some(id) = parent.filter(|id| *id >= root) {
+            if id == root {
+                return true;
+            }
+            parent = self.messages.get(&id).and_then(|message| message.reply_to);
+        }
+        false
+    }
+
+    async fn append(&mut self, record: Record) {
+        if let Some(path) = &self.path {
+            if let Err(err) = write_line(path, &record).await {
+                error!("History writing error: {}", err);
+            }
+            self.records += 1;
+        }
+        self.apply(record);
+        // Stale records outnumbering live ones make the log worth rewriting.
+        if self.records >= MIN_COMPACTED_RECORDS && self.records >= 2 * self.live_records() {
+            self.compact().await;
+        }
+    }
+
+    /// Records of the current state, as written by `compact`.
+    fn live_records(&self) -> usize {
+        self.messages.len() + self.read.len() + 1
+    }
+
+    fn schedule_scrub(&mut self) {
+        if self.path.is_some() && self.scrub_at.is_none() {
+            let delay = self.scrub_delay.as_millis() as u64;
+            self.scrub_at = Some(now_millis().saturating_add(delay));
+        }
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Put { message } => {
+                self.last_id = self.last_id.max(message.id);
+                if let Some(expires_at) = message.expires_at {
+                    self.expiring.insert((expires_at, message.id));
+                }
+                let key = (message.sender.clone(), message.receiver.clone());
+                self.sent.entry(key).or_default().insert(message.id);
+                self.messages.insert(message.id, message);
+            }
+            Record::Delete { id } => {
+                self.forget(id);
+            }
+            Record::Read {
+                reader,
+                peer,
+                up_to,
+            } => {
+                let read_up_to = self.read.entry((reader, peer)).or_default();
+                *read_up_to = (*read_up_to).max(up_to);
+            }
+            Record::LastId { id } => self.last_id = self.last_id.max(id),
+        }
+    }
+
+    fn forget(&mut self, id: u64) -> Option<ServerMessage> {
+        let message = self.messages.remove(&id)?;
+        if let Some(expires_at) = message.expires_at {
+            self.expiring.remove(&(expires_at, id));
+        }
+        let key = (message.sender.clone(), message.receiver.clone());
+        if let Some(ids) = self.sent.get_mut(&key) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.sent.remove(&key);
+            }
+        }
+        Some(message)
+    }
+
+    /// Replaces the log with the records of the current state.
+    #[allow(clippy::diverging_sub_expression)]
+    async fn compact(&mut self) {
+        guard!(let Some(path) = &self.path else { return });
+        let messages = self.messages.values().map(|message| Record::Put {
+            message: message.clone(),
+        });
+        let read = self
+            .read
+            .iter()
+            .map(|((reader, peer), up_to)| Record::Read {
+                reader: reader.clone(),
+                peer: peer.clone(),
+                up_to: *up_to,
+            });
+        let last_id = Record::LastId { id: self.last_id };
+        let records: Vec<_> = messages.chain(read).chain([last_id]).collect();
+        match write_snapshot(path, &records).await {
+            Ok(()) => {
+                self.records = records.len();
+                self.scrub_at = None;
+            }
+            Err(err) => {
+                error!("History compaction error: {}", err);
+                // Tries again after another delay rather than right away.
+                if self.scrub_at.take().is_some() {
+                    self.schedule_scrub();
+                }
+            }
+        }
+    }
+}
+
+async fn write_line(path: &Path, record: &Record) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&line).await?;
+    file.flush().await?;
+    Ok(())
+}
+
+/// Writes `records` next to `path` first, so a crash leaves either log intact.
+async fn write_snapshot(path: &Path, records: &[Record]) -> anyhow::Result<()> {
+    let mut snapshot = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut snapshot, record)?;
+        snapshot.push(b'\n');
+    }
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, &snapshot).await?;
+    fs::rename(&temp, path).await?;
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use guard::guard;
+use log::{error, info};
+use serde_json::json;
+use tokio::{
+    io::{self, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time,
+};
+
+use crate::message::ErrorCode;
+
+use super::{BlobStore, Clients, Config};
+
+//
+// A deliberately tiny HTTP/1.1 endpoint, one request per connection:
+//
+// GET  /blobs/<blob_id>   Downloads a blob the user may read.
+// POST /blobs             Uploads the body as a blob, `Content-Type` and
+//                         `X-File-Name` are kept as its metadata.
+//
+// Requests authenticate with `Authorization: Bearer <http_token>`, as issued
+// in the handshake reply to a connected session.
+//
+
+const MAX_HEAD_LENGTH: u64 = 8 * 1024;
+
+#[allow(clippy::diverging_sub_expression)]
+pub async fn serve(
+    listener: TcpListener,
+    clients: Clients,
+    blobs: Arc<BlobStore>,
+    config: Arc<Config>,
+) {
+    loop {
+        guard!(let Ok((stream, addr)) = listener.accept().await else { continue });
+        let (clients, blobs, config) = (clients.clone(), blobs.clone(), config.clone());
+        tokio::spawn(async move {
+            if let Err(err) = handle(stream, &clients, &blobs, &config).await {
+                info!("HTTP connection from {} failed: {}", addr, err);
+            }
+        });
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    /// Keyed by lowercased names.
+    headers: HashMap<String, String>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// The user the request acts for, as long as their session is connected.
+    fn uid(&self, clients: &Clients) -> Option<String> {
+        let token = self
+            .header("authorization")?
+            .strip_prefix("Bearer ")?
+            .trim();
+        let (uid, secret) = token.rsplit_once('.')?;
+        let client = clients.lock().unwrap().get(uid).cloned()?;
+        client
+            .accepts_http_secret(secret)
+            .then(|| client.uid.clone())
+    }
+}
+
+#[allow(clippy::diverging_sub_expression)]
+async fn handle(
+    stream: TcpStream,
+    clients: &Clients,
+    blobs: &BlobStore,
+    config: &Config,
+) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+    let request = match time::timeout(config.handshake_timeout, read_head(&mut stream)).await {
+        Ok(request) => request?,
+        Err(_) => return Ok(()),
+    };
+    guard!(let Some(request) = request else {
+        return respond(&mut stream, 400, "text/plain", b"Bad request").await
+    });
+    guard!(let Some(uid) = request.uid(clients) else {
+        return respond(&mut stream, 401, "text/plain", b"Unauthorized").await
+    });
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", path) if path.starts_with("/blobs/") => {
+            let blob_id = &path["/blobs/".len()..];
+            match blobs.open(&uid, blob_id).await {
+                Ok((mut file, meta)) => {
+                    write_head(&mut stream, 200, &meta.mime, meta.size).await?;
+                    io::copy(&mut file, &mut stream).await?;
+                    stream.flush().await
+                }
+                Err(code) => respond_error(&mut stream, code).await,
+            }
+        }
+        ("POST", "/blobs") => upload(&mut stream, &request, &uid, blobs, config).await,
+        _ => respond(&mut stream, 404, "text/plain", b"Not found").await,
+    }
+}
+
+#[allow(clippy::diverging_sub_expression)]
+async fn upload<S>(
+    stream: &mut S,
+    request: &Request,
+    uid: &str,
+    blobs: &BlobStore,
+    config: &Config,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let length = request
+        .header("content-length")
+        .and_then(|v| v.parse::<u64>().ok());
+    guard!(let Some(length) = length else {
+        return respond(stream, 411, "text/plain", b"Length required").await
+    });
+    if length > config.max_blob_size {
+        return respond_error(stream, ErrorCode::TooLarge).await;
+    }
+    let name = request
+        .header("x-file-name")
+        .unwrap_or_default()
+        .to_string();
+    let mime = request
+        .header("content-type")
+        .unwrap_or("application/octet-stream")
+        .to_string();
+
+    let put = blobs.put(uid, name, mime, length, &mut *stream);
+    // Whatever arrived of a late body is left for the sweep of idle uploads.
+    let Ok(put) = time::timeout(body_timeout(length, config), put).await else {
+        return respond(stream, 408, "text/plain", b"Request timeout").await;
+    };
+    match put {
+        Ok(blob_id) => {
+            let body = json!({
+                "blob_id": blob_id,
+                "size": length,
+                "url": config.blob_url(&blob_id),
+            });
+            respond(stream, 201, "application/json", body.to_string().as_bytes()).await
+        }
+        Err(code) => respond_error(stream, code).await,
+    }
+}
+
+/// Time a body of `length` bytes gets, the frame timeout plus its transfer at the min throughput.
+fn body_timeout(length: u64, config: &Config) -> Duration {
+    let transfer = match config.min_frame_throughput {
+        0 => Duration::ZERO,
+        min => Duration::from_secs_f64(length as f64 / min as f64),
+    };
+    config.frame_timeout + transfer
+}
+
+/// Reads the request line and headers, `None` if they're malformed.
+#[allow(clippy::diverging_sub_expression)]
+async fn read_head<R>(stream: &mut R) -> io::Result<Option<Request>>
+where
+    R: AsyncBufReadExt + Unpin,
+{
+    let mut head = stream.take(MAX_HEAD_LENGTH);
+    let mut line = String::new();
+    head.read_line(&mut line).await?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Ok(None),
+    };
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if head.read_line(&mut line).await? == 0 {
+            // Ran out of bytes before the blank line.
+            return Ok(None);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        guard!(let Some((name, value)) = header.split_once(':') else { return Ok(None) });
+        headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+    }
+
+    Ok(Some(Request {
+        method,
+        path,
+        headers,
+    }))
+}
+
+async fn write_head<W>(
+    stream: &mut W,
+    status: u16,
+    content_type: &str,
+    length: u64,
+) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason(status),
+        content_type,
+        length
+    );
+    stream.write_all(head.as_bytes()).await
+}
+
+async fn respond<W>(stream: &mut W, status: u16, content_type: &str, body: &[u8]) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    write_head(stream, status, content_type, body.len() as u64).await?;
+    stream.write_all(body).await?;
+    stream.flush().await
+}
+
+async fn respond_error<W>(stream: &mut W, code: ErrorCode) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let status = match code {
+        // Blobs the user may not read are reported as missing.
+        ErrorCode::BlobNotFound => 404,
+        ErrorCode::TooLarge => 413,
+        ErrorCode::MalformedPayload => 400,
+        _ => {
+            error!("HTTP request failed: {}", code);
+            500
+        }
+    };
+    respond(stream, status, "text/plain", code.to_string().as_bytes()).await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    }
+}
@@ -1,77 +1,322 @@
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    net::SocketAddr,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
 };
 
-use log::info;
-use tokio::{net::TcpStream, sync::mpsc};
-use tokio_util::either::Either;
+use guard::guard;
+use log::{error, info};
+use serde::Serialize;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    select,
+    sync::mpsc,
+    task::JoinHandle,
+    time::{self, Instant},
+};
 
 use crate::{
-    frame::{self, SendablePayload},
-    message::{ClientMessage, MessageReply, Ping, Pong, ServerMessage},
+    frame::{self, messages::Inbound, ReceivableSerdePayload, SendableBy, ServerSide},
+    message::{
+        now_millis, Acknowledge, CancelScheduled, Capability, ClientMessage, Content,
+        DeleteMessage, EditMessage, ErrorCode, Handshake, HistoryReply, HistoryRequest, Mention,
+        MessageDeleted, MessageEdited, MessageExpired, MessageReply, PeerTyping, Pong, Reaction,
+        ReactionUpdated, Receipt, ReceiptKind, ScheduleMessage, ScheduledList, ServerMessage,
+        Typing, TypingState,
+    },
 };
 
+mod admission;
+use self::admission::{Admission, Permit};
+
+mod blob_store;
+pub use self::blob_store::{BlobMeta, BlobStore, ImageMeta, ThumbnailMeta};
+
 mod client;
 pub use self::client::Client;
 
 mod client_task;
 pub use self::client_task::ClientTask;
 
+mod config;
+pub use self::config::{Config, Rate, RateLimits};
+
+mod history;
+pub use self::history::History;
+
+mod http;
+
+mod rate_limit;
+use self::rate_limit::RateLimiter;
+
+mod schedule;
+use self::schedule::Schedule;
+
+mod thumbnail;
+
+mod transfer;
+
+mod typing;
+use self::typing::Typists;
+
 #[derive(Debug)]
 pub struct Item {
     client: Arc<Client>,
-    message: frame::Result<Either<ClientMessage, Ping>>,
+    message: frame::Result<Inbound>,
 }
 
 impl Item {
-    pub fn new(client: Arc<Client>, message: frame::Result<Either<ClientMessage, Ping>>) -> Self {
+    pub fn new(client: Arc<Client>, message: frame::Result<Inbound>) -> Self {
         Self { client, message }
     }
 }
 
 pub type Entry = mpsc::Sender<Item>;
 pub type Clients = Arc<Mutex<HashMap<String, Arc<Client>>>>;
-pub type Reader = frame::Reader<tokio::net::tcp::OwnedReadHalf>;
-pub type Writer = frame::Writer<tokio::net::tcp::OwnedWriteHalf>;
+pub type Reader = frame::Reader<tokio::net::tcp::OwnedReadHalf, ServerSide>;
+pub type Writer = frame::Writer<tokio::net::tcp::OwnedWriteHalf, ServerSide>;
+
+/// Upper bound of messages in a `HistoryReply`.
+const MAX_HISTORY_LIMIT: usize = 100;
 
-type Sender = mpsc::Sender<Box<dyn SendablePayload>>;
-type Receiver = mpsc::Receiver<Box<dyn SendablePayload>>;
+/// Upper bound of messages a user may have scheduled at once.
+const MAX_SCHEDULED: usize = 100;
+
+/// Upper bound of distinct emoji reacted to a message with.
+const MAX_REACTIONS: usize = 20;
+
+type Sender = mpsc::Sender<Box<dyn SendableBy<ServerSide>>>;
+type Receiver = mpsc::Receiver<Box<dyn SendableBy<ServerSide>>>;
 
 pub struct Handler {
     entry: Entry,
     clients: Clients,
+    blobs: Arc<BlobStore>,
+    admission: Arc<Admission>,
+    limiter: Arc<RateLimiter>,
+    config: Arc<Config>,
+    /// The run loop, which ends once every entry is dropped.
+    run_task: JoinHandle<()>,
+    /// Tasks serving alongside the connections, aborted on shutdown.
+    background: Vec<JoinHandle<()>>,
 }
 
 impl Handler {
     pub fn run() -> Handler {
+        Self::run_with_config(Config::default())
+    }
+
+    pub fn run_with_config(config: Config) -> Handler {
         let (entry, receiver) = mpsc::channel(256);
-        let handler = Self {
+        let config = Arc::new(config);
+        let clients = Clients::default();
+        let blobs = Arc::new(BlobStore::new(&config.blob_dir));
+        let run_task = tokio::spawn(run(
+            clients.clone(),
+            blobs.clone(),
+            config.clone(),
+            receiver,
+        ));
+        let sweep_task = tokio::spawn(remove_idle_uploads(
+            Arc::downgrade(&blobs),
+            config.upload_idle_timeout,
+        ));
+        Self {
             entry,
-            clients: Default::default(),
-        };
-        tokio::spawn(run(handler.clients.clone(), receiver));
-        handler
+            clients,
+            blobs,
+            admission: Arc::new(Admission::new(config.clone())),
+            limiter: Arc::new(RateLimiter::new(config.clone())),
+            config,
+            run_task,
+            background: vec![sweep_task],
+        }
     }
 
-    pub fn connect(&self, stream: TcpStream) {
+    /// Serves the connection from `addr`, or closes it right away if over the limits.
+    pub fn connect(&self, stream: TcpStream, addr: SocketAddr) {
+        let permit = match self.admission.admit(addr) {
+            Ok(permit) => permit,
+            Err(rejection) => {
+                info!("Rejecting {}: {}", addr, rejection);
+                return;
+            }
+        };
         let entry = self.entry.clone();
         let clients = self.clients.clone();
-        tokio::spawn(ClientTask::run(stream, entry, clients));
+        let blobs = self.blobs.clone();
+        let limiter = self.limiter.clone();
+        let config = self.config.clone();
+        tokio::spawn(ClientTask::run(
+            stream, permit, entry, clients, blobs, limiter, config,
+        ));
+    }
+
+    /// Serves blobs over HTTP on `listener`.
+    pub fn serve_http(&mut self, listener: TcpListener) {
+        let clients = self.clients.clone();
+        let blobs = self.blobs.clone();
+        let config = self.config.clone();
+        let task = tokio::spawn(http::serve(listener, clients, blobs, config));
+        self.background.push(task);
+    }
+
+    /// Closes every connection, then waits for the run loop to finish its last writes.
+    pub async fn shutdown(self) {
+        for task in &self.background {
+            task.abort();
+        }
+        self.admission.close_all();
+        drop(self.entry);
+        if let Err(err) = self.run_task.await {
+            error!("Run loop failed: {}", err);
+        }
+    }
+}
+
+/// Removes abandoned uploads at start, then often enough to keep none much longer than `idle`.
+async fn remove_idle_uploads(blobs: Weak<BlobStore>, idle: Duration) {
+    let mut interval = time::interval((idle / 4).max(Duration::from_millis(10)));
+    loop {
+        interval.tick().await;
+        // Stops along with the server.
+        let Some(blobs) = blobs.upgrade() else {
+            break;
+        };
+        // Failures are logged by the store, the next sweep tries again.
+        let _ = blobs.remove_idle_uploads(idle).await;
+    }
+}
+
+/// State owned by the run loop.
+struct State {
+    clients: Clients,
+    blobs: Arc<BlobStore>,
+    history: History,
+    schedule: Schedule,
+    typists: Typists,
+    config: Arc<Config>,
+}
+
+impl State {
+    fn client(&self, uid: &str) -> Option<Arc<Client>> {
+        self.clients.lock().unwrap().get(uid).cloned()
+    }
+
+    /// Sends `payload` to whichever sides of the message's conversation are online.
+    async fn send_to_both<T>(&self, message: &ServerMessage, payload: T)
+    where
+        T: SendableBy<ServerSide> + Clone + 'static,
+    {
+        for client in self.online_sides(message) {
+            client.send(payload.clone()).await;
+        }
+    }
+
+    /// Sends an event of `capability` to the sides of the conversation which negotiated it.
+    async fn notify_both<T>(&self, message: &ServerMessage, capability: Capability, event: T)
+    where
+        T: SendableBy<ServerSide> + Clone + 'static,
+    {
+        for client in self.online_sides(message) {
+            client.notify(capability, event.clone()).await;
+        }
+    }
+
+    fn online_sides(&self, message: &ServerMessage) -> Vec<Arc<Client>> {
+        let mut uids = vec![&message.sender, &message.receiver];
+        uids.dedup();
+        uids.into_iter()
+            .filter_map(|uid| self.client(uid))
+            .collect()
     }
 }
 
-async fn run(clients: Clients, mut receiver: mpsc::Receiver<Item>) {
-    while let Some(item) = receiver.recv().await {
-        handle_item(item, clients.clone()).await;
+#[allow(clippy::diverging_sub_expression)]
+async fn run(
+    clients: Clients,
+    blobs: Arc<BlobStore>,
+    config: Arc<Config>,
+    mut receiver: mpsc::Receiver<Item>,
+) {
+    let history = History::load(config.history_path.clone(), config.history_scrub_delay).await;
+    let schedule = Schedule::load(config.schedule_path.clone()).await;
+    let typists = Typists::new(config.typing_throttle, config.typing_timeout);
+    let mut state = State {
+        clients,
+        blobs,
+        history,
+        schedule,
+        typists,
+        config,
+    };
+    loop {
+        let typing_expiry = state.typists.next_expiry();
+        let message_expiry = state.history.next_expiry().map(instant_of);
+        let scrub = state.history.next_scrub().map(instant_of);
+        let next_due = state.schedule.next_due().map(instant_of);
+        select! {
+            item = receiver.recv() => {
+                guard!(let Some(item) = item else { break });
+                handle_item(item, &mut state).await;
+            }
+            _ = sleep_until(typing_expiry), if typing_expiry.is_some() => {
+                expire_typing(&mut state).await;
+            }
+            _ = sleep_until(message_expiry), if message_expiry.is_some() => {
+                expire_messages(&mut state).await;
+            }
+            _ = sleep_until(scrub), if scrub.is_some() => {
+                state.history.scrub().await;
+            }
+            _ = sleep_until(next_due), if next_due.is_some() => {
+                send_scheduled(&mut state).await;
+            }
+        }
     }
 }
 
-async fn handle_item(item: Item, clients: Clients) {
+/// Sleeps until `deadline`, disabled `select!` branches still build one.
+fn sleep_until(deadline: Option<Instant>) -> time::Sleep {
+    time::sleep_until(deadline.unwrap_or_else(Instant::now))
+}
+
+/// `millis` since the Unix epoch on the monotonic clock.
+fn instant_of(millis: u64) -> Instant {
+    Instant::now() + Duration::from_millis(millis.saturating_sub(now_millis()))
+}
+
+async fn handle_item(item: Item, state: &mut State) {
     match item.message {
         Ok(msg) => match msg {
-            Either::Left(msg) => handle_message(msg, item.client, clients).await,
-            Either::Right(_) => handle_ping(item.client).await,
+            Inbound::ClientMessage(msg) => handle_message(msg, item.client, state).await,
+            Inbound::EditMessage(edit) => handle_edit(edit, item.client, state).await,
+            Inbound::DeleteMessage(delete) => handle_delete(delete, item.client, state).await,
+            Inbound::HistoryRequest(request) => handle_history(request, item.client, state).await,
+            Inbound::Acknowledge(ack) => handle_acknowledge(ack, item.client, state).await,
+            Inbound::AddReaction(add) => {
+                handle_reaction(add.id, add.emoji, true, item.client, state).await
+            }
+            Inbound::RemoveReaction(remove) => {
+                handle_reaction(remove.id, remove.emoji, false, item.client, state).await
+            }
+            Inbound::ScheduleMessage(schedule) => {
+                handle_schedule(schedule, item.client, state).await
+            }
+            Inbound::ListScheduled(_) => handle_list_scheduled(item.client, state).await,
+            Inbound::CancelScheduled(cancel) => {
+                handle_cancel_scheduled(cancel, item.client, state).await
+            }
+            Inbound::Typing(typing) => handle_typing(typing, item.client, state).await,
+            Inbound::Ping(_) => handle_ping(item.client).await,
+            // Handshake is only expected once, before the run loop.
+            Inbound::Handshake(_) => {
+                let err = frame::Error::TypeMismatch(Handshake::type_code());
+                handle_error(err, item.client).await
+            }
+            // Transfers are served by the client task, off the shared loop.
+            Inbound::UploadRequest(_) | Inbound::UploadChunk(_) | Inbound::DownloadRequest(_) => (),
         },
         Err(err) => handle_error(err, item.client).await,
     }
@@ -81,24 +326,406 @@ async fn handle_ping(sender: Arc<Client>) {
     sender.send(Pong).await
 }
 
-async fn handle_message(message: ClientMessage, sender: Arc<Client>, clients: Clients) {
+async fn handle_message(message: ClientMessage, sender: Arc<Client>, state: &mut State) {
+    if let Err(reply) = deliver(message, &sender, None, state).await {
+        sender.send(reply).await
+    }
+}
+
+/// Stores `message` and sends it to both sides, after the success reply to the
+/// sender; returns the failure reply instead if it can't go through. Replies of a
+/// scheduled message carry its `schedule_id`.
+async fn deliver(
+    message: ClientMessage,
+    sender: &Client,
+    schedule_id: Option<u64>,
+    state: &mut State,
+) -> Result<(), MessageReply> {
     info!("Msg: {:?}", message);
-    let receiver = clients.lock().unwrap().get(&message.receiver).cloned();
-    if let Some(receiver) = receiver {
-        // 1. Send reply to sender.
-        let reply = MessageReply::success(None);
-        sender.send(reply).await;
-        // 2. Send message to sender & receiver.
-        let message = ServerMessage::new(message.content, sender.uid.clone(), receiver.uid.clone());
-        sender.send(message.clone()).await;
-        receiver.send(message).await;
+    let Some(receiver) = state.client(&message.receiver) else {
+        return Err(MessageReply::failed(ErrorCode::ReceiverNotFound, None));
+    };
+    let ClientMessage {
+        content,
+        fallback,
+        reply_to,
+        ttl,
+        ..
+    } = message;
+    if let Some(id) = reply_to {
+        let replied = state.history.get(id);
+        if !replied.is_some_and(|m| m.is_between(&sender.uid, &receiver.uid)) {
+            return Err(MessageReply::failed(
+                ErrorCode::MessageNotFound,
+                Some("Replied message not found".into()),
+            ));
+        }
+    }
+    if ttl == Some(0) {
+        return Err(MessageReply::failed(
+            ErrorCode::MalformedPayload,
+            Some("TTL must be positive".into()),
+        ));
+    }
+    // Nothing is shared for a message that goes nowhere.
+    let (blobs, config) = (&state.blobs, &state.config);
+    if let Err(code) = transfer::grant(&content, &sender.uid, &receiver.uid, blobs, config).await {
+        return Err(MessageReply::failed(code, None));
+    }
+    let mut message = ServerMessage::new(content, sender.uid.clone(), receiver.uid.clone());
+    message.fallback = fallback;
+    message.reply_to = reply_to;
+    message.expires_at = ttl.map(|ttl| message.timestamp.saturating_add(ttl));
+    let message = state.history.insert(message).await;
+    // The message supersedes the indicator on the receiver's side.
+    state.typists.clear(&message.sender, &message.receiver);
+    // 1. Send reply to sender.
+    let mut reply = MessageReply::success(None);
+    reply.put_extra("id", message.id.into());
+    if let Some(schedule_id) = schedule_id {
+        reply.put_extra("schedule_id", schedule_id.into());
+    }
+    sender.send(reply).await;
+    // 2. Send message to sender & receiver.
+    sender.send(message.clone()).await;
+    let mentioned = message.content.mentions(&receiver.uid);
+    let id = message.id;
+    receiver.send(message).await;
+    // 3. Notify the receiver if mentioned.
+    if mentioned {
+        let unread_mentions = state.history.unread_mentions(&receiver.uid, &sender.uid);
+        let event = Mention {
+            id,
+            sender: sender.uid.clone(),
+            unread_mentions,
+        };
+        receiver.notify(Capability::Mentions, event).await;
+    }
+    Ok(())
+}
+
+async fn handle_schedule(schedule: ScheduleMessage, sender: Arc<Client>, state: &mut State) {
+    let ScheduleMessage {
+        deliver_at,
+        message,
+    } = schedule;
+    if deliver_at <= now_millis() {
+        let reply = MessageReply::failed(
+            ErrorCode::MalformedPayload,
+            Some("Delivery time has passed".into()),
+        );
+        return sender.send(reply).await;
+    }
+    if state.schedule.pending_for(&sender.uid).len() >= MAX_SCHEDULED {
+        let reply = MessageReply::failed(
+            ErrorCode::TooLarge,
+            Some("Too many scheduled messages".into()),
+        );
+        return sender.send(reply).await;
+    }
+
+    let scheduled = state.schedule.add(&sender.uid, deliver_at, message).await;
+    let mut reply = MessageReply::success(None);
+    reply.put_extra("schedule_id", scheduled.id.into());
+    sender.send(reply).await
+}
+
+async fn handle_list_scheduled(sender: Arc<Client>, state: &mut State) {
+    let messages = state.schedule.pending_for(&sender.uid);
+    sender.send(ScheduledList { messages }).await
+}
+
+async fn handle_cancel_scheduled(cancel: CancelScheduled, sender: Arc<Client>, state: &mut State) {
+    let reply = if state.schedule.cancel(&sender.uid, cancel.id).await {
+        MessageReply::success(None)
     } else {
-        let reply = MessageReply::failed(Some("Receiver not found".to_string()));
-        sender.send(reply).await
+        MessageReply::failed(ErrorCode::MessageNotFound, None)
+    };
+    sender.send(reply).await
+}
+
+/// Sends the scheduled messages now due, as if their senders just did.
+///
+/// One whose receiver is offline stays pending and is tried again later, other
+/// failures are kept for the sender to see in `ListScheduled` until cancelled.
+async fn send_scheduled(state: &mut State) {
+    let now = now_millis();
+    for pending in state.schedule.due(now) {
+        let id = pending.scheduled.id;
+        let last_error = pending.scheduled.error;
+        let sender = state
+            .client(&pending.sender)
+            .unwrap_or_else(|| Arc::new(Client::offline(pending.sender)));
+        match deliver(pending.scheduled.message, &sender, Some(id), state).await {
+            Ok(()) => state.schedule.sent(id).await,
+            Err(mut reply) => {
+                let error = reply.code.unwrap_or(ErrorCode::Internal);
+                let retry_at = (error == ErrorCode::ReceiverNotFound)
+                    .then(|| now.saturating_add(state.config.schedule_retry.as_millis() as u64));
+                state.schedule.failed(id, error, retry_at).await;
+                // Retries failing the same way aren't news to the sender.
+                if last_error != Some(error) {
+                    reply.put_extra("schedule_id", id.into());
+                    sender.send(reply).await;
+                }
+            }
+        }
+    }
+}
+
+async fn handle_edit(edit: EditMessage, sender: Arc<Client>, state: &mut State) {
+    let EditMessage { id, content } = edit;
+    let mut message = match own_message(id, &sender, state) {
+        Ok(message) => message,
+        Err(reply) => return sender.send(reply).await,
+    };
+    if !matches!(
+        (&message.content, &content),
+        (Content::Text(_), Content::Text(_))
+    ) {
+        let reply = MessageReply::failed(
+            ErrorCode::Forbidden,
+            Some("Only text messages can be edited".into()),
+        );
+        return sender.send(reply).await;
+    }
+
+    let edited_at = now_millis();
+    message.content = content.clone();
+    message.edited_at = Some(edited_at);
+    state.history.put(message.clone()).await;
+    sender.send(MessageReply::success(None)).await;
+    let event = MessageEdited {
+        id,
+        content,
+        edited_at,
+    };
+    state.send_to_both(&message, event).await
+}
+
+async fn handle_delete(delete: DeleteMessage, sender: Arc<Client>, state: &mut State) {
+    let message = match own_message(delete.id, &sender, state) {
+        Ok(message) => message,
+        Err(reply) => return sender.send(reply).await,
+    };
+    let age = now_millis().saturating_sub(message.timestamp);
+    if age > state.config.recall_window.as_millis() as u64 {
+        let reply = MessageReply::failed(ErrorCode::RecallExpired, None);
+        return sender.send(reply).await;
+    }
+
+    state.history.remove(message.id).await;
+    sender.send(MessageReply::success(None)).await;
+    state
+        .send_to_both(&message, MessageDeleted { id: message.id })
+        .await
+}
+
+async fn handle_reaction(
+    id: u64,
+    emoji: String,
+    add: bool,
+    sender: Arc<Client>,
+    state: &mut State,
+) {
+    let mut message = match state.history.get(id) {
+        Some(message) if message.sender == sender.uid || message.receiver == sender.uid => {
+            message.clone()
+        }
+        _ => {
+            let reply = MessageReply::failed(ErrorCode::MessageNotFound, None);
+            return sender.send(reply).await;
+        }
+    };
+    if add && !Reaction::is_valid_emoji(&emoji) {
+        let reply = MessageReply::failed(
+            ErrorCode::MalformedPayload,
+            Some(format!("Not an emoji: {:?}", emoji)),
+        );
+        return sender.send(reply).await;
+    }
+    let is_new = !message.reactions.iter().any(|r| r.emoji == emoji);
+    if add && is_new && message.reactions.len() >= MAX_REACTIONS {
+        let reply = MessageReply::failed(ErrorCode::TooLarge, Some("Too many reactions".into()));
+        return sender.send(reply).await;
+    }
+
+    let changed = if add {
+        message.add_reaction(&emoji, &sender.uid)
+    } else {
+        message.remove_reaction(&emoji, &sender.uid)
+    };
+    sender.send(MessageReply::success(None)).await;
+    if changed {
+        state.history.put(message.clone()).await;
+        let event = ReactionUpdated {
+            id,
+            reactions: message.reactions.clone(),
+        };
+        state
+            .notify_both(&message, Capability::Reactions, event)
+            .await
+    }
+}
+
+/// The message with `id`, as long as `sender` sent it.
+fn own_message(id: u64, sender: &Client, state: &State) -> Result<ServerMessage, MessageReply> {
+    match state.history.get(id) {
+        Some(message) if message.sender == sender.uid => Ok(message.clone()),
+        Some(message) if message.receiver == sender.uid => {
+            Err(MessageReply::failed(ErrorCode::Forbidden, None))
+        }
+        // Others' conversations are as good as missing.
+        _ => Err(MessageReply::failed(ErrorCode::MessageNotFound, None)),
     }
 }
 
+async fn handle_acknowledge(ack: Acknowledge, sender: Arc<Client>, state: &mut State) {
+    let message = match state.history.get(ack.id) {
+        Some(message) if message.receiver == sender.uid => message,
+        _ => {
+            let reply = MessageReply::failed(ErrorCode::MessageNotFound, None);
+            return sender.send(reply).await;
+        }
+    };
+    let peer = message.sender.clone();
+    if ack.kind == ReceiptKind::Read && !state.history.mark_read(&sender.uid, &peer, ack.id).await {
+        // Reading an older message again tells the peer nothing new.
+        return;
+    }
+    if let Some(peer) = state.client(&peer) {
+        let receipt = Receipt {
+            kind: ack.kind,
+            id: ack.id,
+            by: sender.uid.clone(),
+        };
+        peer.notify(Capability::Receipts, receipt).await
+    }
+}
+
+#[allow(clippy::diverging_sub_expression)]
+async fn handle_typing(typing: Typing, sender: Arc<Client>, state: &mut State) {
+    // Nobody to tell, and nothing to reply.
+    guard!(let Some(receiver) = state.client(&typing.receiver) else { return });
+    if state
+        .typists
+        .update(&sender.uid, &receiver.uid, typing.state)
+    {
+        let event = PeerTyping {
+            sender: sender.uid.clone(),
+            state: typing.state,
+        };
+        receiver.notify(Capability::Typing, event).await
+    }
+}
+
+async fn expire_typing(state: &mut State) {
+    for (sender, receiver) in state.typists.expire() {
+        if let Some(receiver) = state.client(&receiver) {
+            let event = PeerTyping {
+                sender,
+                state: TypingState::Stopped,
+            };
+            receiver.notify(Capability::Typing, event).await
+        }
+    }
+}
+
+async fn expire_messages(state: &mut State) {
+    for message in state.history.expire(now_millis()).await {
+        let event = MessageExpired { id: message.id };
+        state
+            .notify_both(&message, Capability::Ephemeral, event)
+            .await
+    }
+}
+
+async fn handle_history(request: HistoryRequest, sender: Arc<Client>, state: &mut State) {
+    let limit = request.limit.min(MAX_HISTORY_LIMIT);
+    // One more than asked for tells whether there are older ones.
+    let mut messages = match request.thread {
+        // Threads of other conversations are as good as empty.
+        Some(root) => match state.history.get(root) {
+            Some(message) if message.is_between(&sender.uid, &request.peer) => {
+                state.history.thread(root, request.before, limit + 1)
+            }
+            _ => Vec::new(),
+        },
+        None => state
+            .history
+            .conversation(&sender.uid, &request.peer, request.before, limit + 1),
+    };
+    let has_more = messages.len() > limit;
+    if has_more {
+        messages.remove(0);
+    }
+    let history = &state.history;
+    let mut reply = HistoryReply {
+        read_up_to: history.read_up_to(&sender.uid, &request.peer),
+        peer_read_up_to: history.read_up_to(&request.peer, &sender.uid),
+        unread: history.unread(&sender.uid, &request.peer),
+        unread_mentions: history.unread_mentions(&sender.uid, &request.peer),
+        peer: request.peer,
+        messages: Vec::new(),
+        has_more,
+    };
+    if !fit_history(&mut reply, messages, state.config.max_payload_length) {
+        let reply = MessageReply::failed(
+            ErrorCode::TooLarge,
+            Some("Message too large for a payload".into()),
+        );
+        return sender.send(reply).await;
+    }
+    sender.send(reply).await
+}
+
+/// Fills `reply` with the newest of `messages` which fit in a payload, `false` if
+/// not even one does.
+///
+/// Lengths are taken in JSON, which no other encoding exceeds.
+fn fit_history(
+    reply: &mut HistoryReply,
+    mut messages: Vec<ServerMessage>,
+    max_payload_length: usize,
+) -> bool {
+    let mut length = json_length(reply);
+    let fitting = messages
+        .iter()
+        .rev()
+        .take_while(|message| {
+            // Each takes a comma too, the first one a spare.
+            length = length.saturating_add(json_length(message) + 1);
+            length <= max_payload_length
+        })
+        .count();
+    if fitting < messages.len() {
+        reply.has_more = true;
+    }
+    reply.messages = messages.split_off(messages.len() - fitting);
+    fitting > 0 || messages.is_empty()
+}
+
+fn json_length<T: Serialize>(value: &T) -> usize {
+    serde_json::to_vec(value).map_or(usize::MAX, |json| json.len())
+}
+
+/// Turns inbound content failing validation into the reply explaining why.
+///
+/// Done on the client task ahead of anything else, so nothing looks into what invalid
+/// content refers to.
+fn check_content(msg: &Inbound) -> Result<(), MessageReply> {
+    let content = match msg {
+        Inbound::ClientMessage(message) => &message.content,
+        Inbound::ScheduleMessage(schedule) => &schedule.message.content,
+        Inbound::EditMessage(edit) => &edit.content,
+        _ => return Ok(()),
+    };
+    content
+        .validate()
+        .map_err(|reason| MessageReply::failed(ErrorCode::MalformedPayload, Some(reason)))
+}
+
 async fn handle_error(err: frame::Error, sender: Arc<Client>) {
-    let reply = MessageReply::failed(Some(err.to_string()));
+    let reply = MessageReply::error(err);
     sender.send(reply).await
 }
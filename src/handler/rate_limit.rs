@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use super::{Config, Rate};
+
+/// Number of buckets beyond which the full, idle ones are dropped.
+const PRUNE_THRESHOLD: usize = 4096;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: Rate) -> Self {
+        Self {
+            tokens: rate.burst as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Takes a token, or tells how long until there is one.
+    pub fn take(&mut self, rate: Rate) -> Result<(), Duration> {
+        self.wait(rate)?;
+        self.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Refills, then tells how long until there is a token, if not now.
+    fn wait(&mut self, rate: Rate) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_second).min(rate.burst as f64);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            Ok(())
+        } else if rate.per_second > 0.0 {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / rate.per_second,
+            ))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    fn is_full(&mut self, rate: Rate) -> bool {
+        self.wait(rate).is_ok() && self.tokens >= rate.burst as f64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    User(String),
+    Ip(IpAddr),
+}
+
+/// Token buckets of every user and IP, by type code.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: Arc<Config>,
+    buckets: Mutex<HashMap<(Key, Option<u8>), TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self {
+            config,
+            buckets: Default::default(),
+        }
+    }
+
+    /// Lets a frame of `type_code` through if both `uid` and `ip` have a token left,
+    /// otherwise tells how long until they do.
+    pub fn check(&self, uid: &str, ip: IpAddr, type_code: Option<u8>) -> Result<(), Duration> {
+        let limits = [
+            (
+                Key::User(uid.into()),
+                self.config.user_rate_limits.rate(type_code),
+            ),
+            (Key::Ip(ip), self.config.ip_rate_limits.rate(type_code)),
+        ];
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_THRESHOLD {
+            let config = &self.config;
+            buckets.retain(|(key, type_code), bucket| {
+                let limits = match key {
+                    Key::User(_) => &config.user_rate_limits,
+                    Key::Ip(_) => &config.ip_rate_limits,
+                };
+                !bucket.is_full(limits.rate(*type_code))
+            });
+        }
+        // Nothing is taken unless every bucket has a token.
+        let mut wait = Duration::ZERO;
+        for (key, rate) in &limits {
+            let bucket = buckets
+                .entry((key.clone(), type_code))
+                .or_insert_with(|| TokenBucket::new(*rate));
+            if let Err(duration) = bucket.wait(*rate) {
+                wait = wait.max(duration);
+            }
+        }
+        if wait > Duration::ZERO {
+            return Err(wait);
+        }
+        for (key, rate) in limits {
+            let bucket = buckets.get_mut(&(key, type_code)).unwrap();
+            bucket.take(rate)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use guard::guard;
+use log::error;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::message::{ClientMessage, ErrorCode, ScheduledMessage};
+
+/// Messages waiting to be sent, keyed by id.
+///
+/// With a path, the whole schedule is saved there as JSON on every change; it stays
+/// small as each user may only have so many pending.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    last_id: u64,
+    pending: BTreeMap<u64, Pending>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pending {
+    pub sender: String,
+    pub scheduled: ScheduledMessage,
+    /// When a failed message is tried again, `None` once it failed for good.
+    #[serde(default)]
+    retry_at: Option<u64>,
+}
+
+impl Pending {
+    fn due_at(&self) -> Option<u64> {
+        match self.scheduled.error {
+            None => Some(self.scheduled.deliver_at),
+            Some(_) => self.retry_at,
+        }
+    }
+}
+
+impl Schedule {
+    pub async fn load(path: Option<PathBuf>) -> Self {
+        let saved = match &path {
+            Some(path) => fs::read(path).await.ok(),
+            None => None,
+        };
+        let schedule = saved.and_then(|saved| match serde_json::from_slice(&saved) {
+            Ok(schedule) => Some(schedule),
+            Err(err) => {
+                error!("Discarding schedule: {}", err);
+                None
+            }
+        });
+        Self {
+            path,
+            ..schedule.unwrap_or_default()
+        }
+    }
+
+    pub async fn add(
+        &mut self,
+        sender: &str,
+        deliver_at: u64,
+        message: ClientMessage,
+    ) -> ScheduledMessage {
+        self.last_id += 1;
+        let scheduled = ScheduledMessage {
+            id: self.last_id,
+            deliver_at,
+            message,
+            error: None,
+        };
+        let pending = Pending {
+            sender: sender.into(),
+            scheduled: scheduled.clone(),
+            retry_at: None,
+        };
+        self.pending.insert(scheduled.id, pending);
+        self.save().await;
+        scheduled
+    }
+
+    /// Drops a message `sender` scheduled, `false` if there's none with `id`.
+    pub async fn cancel(&mut self, sender: &str, id: u64) -> bool {
+        match self.pending.get(&id) {
+            Some(pending) if pending.sender == sender => {
+                self.pending.remove(&id);
+                self.save().await;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Messages `sender` scheduled, soonest first.
+    pub fn pending_for(&self, sender: &str) -> Vec<ScheduledMessage> {
+        let mut messages: Vec<_> = self
+            .pending
+            .values()
+            .filter(|pending| pending.sender == sender)
+            .map(|pending| pending.scheduled.clone())
+            .collect();
+        messages.sort_by_key(|message| (message.deliver_at, message.id));
+        messages
+    }
+
+    /// When the next message is due, in milliseconds since the Unix epoch.
+    pub fn next_due(&self) -> Option<u64> {
+        self.pending.values().filter_map(Pending::due_at).min()
+    }
+
+    /// The messages due by `now`, in order; they stay pending until `sent` or
+    /// `failed` is called with their id.
+    pub fn due(&self, now: u64) -> Vec<Pending> {
+        let mut due: Vec<_> = self
+            .pending
+            .values()
+            .filter(|pending| pending.due_at().is_some_and(|due_at| due_at <= now))
+            .cloned()
+            .collect();
+        due.sort_by_key(|pending| (pending.due_at(), pending.scheduled.id));
+        due
+    }
+
+    /// Drops a message once it's sent.
+    pub async fn sent(&mut self, id: u64) {
+        if self.pending.remove(&id).is_some() {
+            self.save().await;
+        }
+    }
+
+    /// Keeps a message which couldn't be sent, to be tried again at `retry_at` if
+    /// any; otherwise it stays listed with `error` until its sender cancels it.
+    pub async fn failed(&mut self, id: u64, error: ErrorCode, retry_at: Option<u64>) {
+        let Some(pending) = self.pending.get_mut(&id) else {
+            return;
+        };
+        pending.scheduled.error = Some(error);
+        pending.retry_at = retry_at;
+        self.save().await;
+    }
+}
+
+impl Schedule {
+    #[allow(clippy::diverging_sub_expression)]
+    async fn save(&self) {
+        guard!(let Some(path) = &self.path else { return });
+        let result = async {
+            let saved = serde_json::to_vec(self)?;
+            // Written aside first, so a crash leaves either version intact.
+            let temp = path.with_extension("tmp");
+            fs::write(&temp, saved).await?;
+            fs::rename(&temp, path).await?;
+            anyhow::Ok(())
+        };
+        if let Err(err) = result.await {
+            error!("Schedule saving error: {}", err);
+        }
+    }
+}
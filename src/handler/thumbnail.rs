@@ -0,0 +1,157 @@
+use std::{io::Cursor, path::Path};
+
+use guard::guard;
+use image::{ImageFormat, ImageReader, Limits};
+use tokio::task;
+
+use crate::message::{Content, ErrorCode, MessageReply, Thumbnail};
+
+use super::{BlobStore, Config, ImageMeta, ThumbnailMeta};
+
+/// Formats accepted for images stored on the server.
+const SUPPORTED_FORMATS: &[ImageFormat] = &[ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Gif];
+
+/// Checks an image stored on the server against what the message declares and
+/// attaches its thumbnails, decoding it on first use.
+///
+/// Nobody is let in to the thumbnails until `grant`.
+#[allow(clippy::diverging_sub_expression)]
+pub async fn attach(
+    content: &mut Content,
+    blob_id: &str,
+    sender: &str,
+    blobs: &BlobStore,
+    config: &Config,
+) -> Result<(), MessageReply> {
+    guard!(let Content::Image { width, height, thumbnails, .. } = content else { return Ok(()) });
+    let meta = blobs.meta(sender, blob_id).await.map_err(failed)?;
+    let image = match meta.image {
+        Some(image) => image,
+        None => validate(blob_id, sender, blobs, config).await?,
+    };
+    if *width != image.width as f64 || *height != image.height as f64 {
+        let message = format!(
+            "Declared {}x{}, actually {}x{}",
+            width, height, image.width, image.height
+        );
+        return Err(MessageReply::failed(ErrorCode::InvalidImage, Some(message)));
+    }
+
+    let mut attached = Vec::with_capacity(image.thumbnails.len());
+    for thumbnail in image.thumbnails {
+        guard!(let Some(url) = config.blob_url(&thumbnail.blob_id) else { continue });
+        attached.push(Thumbnail {
+            url,
+            width: thumbnail.width,
+            height: thumbnail.height,
+        });
+    }
+    *thumbnails = attached;
+    Ok(())
+}
+
+/// Lets both sides of an accepted message read the thumbnails of its image.
+pub async fn grant(
+    blob_id: &str,
+    sender: &str,
+    receiver: &str,
+    blobs: &BlobStore,
+) -> Result<(), ErrorCode> {
+    let meta = blobs.meta(sender, blob_id).await?;
+    let thumbnails = meta.image.map(|image| image.thumbnails).unwrap_or_default();
+    for thumbnail in thumbnails {
+        for uid in [sender, receiver] {
+            blobs.allow(&thumbnail.blob_id, uid).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the image and stores its thumbnails, the outcome is kept in the blob's metadata.
+async fn validate(
+    blob_id: &str,
+    owner: &str,
+    blobs: &BlobStore,
+    config: &Config,
+) -> Result<ImageMeta, MessageReply> {
+    let path = blobs.content_path(blob_id);
+    let max_dimension = config.max_image_dimension;
+    let sizes = config.thumbnail_sizes.clone();
+    let decoded = task::spawn_blocking(move || decode(&path, max_dimension, &sizes))
+        .await
+        .map_err(|_| MessageReply::failed(ErrorCode::Internal, None))?
+        .map_err(|message| MessageReply::failed(ErrorCode::InvalidImage, Some(message)))?;
+
+    let mut thumbnails = Vec::with_capacity(decoded.thumbnails.len());
+    for (width, height, png) in decoded.thumbnails {
+        let name = format!("{}_{}x{}.png", blob_id, width, height);
+        let size = png.len() as u64;
+        let blob_id = blobs
+            .put(owner, name, "image/png".into(), size, &png[..])
+            .await
+            .map_err(failed)?;
+        thumbnails.push(ThumbnailMeta {
+            blob_id,
+            width,
+            height,
+        });
+    }
+    let image = ImageMeta {
+        width: decoded.width,
+        height: decoded.height,
+        thumbnails,
+    };
+    blobs
+        .set_image(blob_id, image.clone())
+        .await
+        .map_err(failed)?;
+    Ok(image)
+}
+
+struct Decoded {
+    width: u32,
+    height: u32,
+    /// Width, height and PNG content of each thumbnail.
+    thumbnails: Vec<(u32, u32, Vec<u8>)>,
+}
+
+/// Blocks, the error is a description for the sender.
+fn decode(path: &Path, max_dimension: u32, sizes: &[u32]) -> Result<Decoded, String> {
+    let mut reader = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|err| err.to_string())?;
+    match reader.format() {
+        Some(format) if SUPPORTED_FORMATS.contains(&format) => (),
+        Some(format) => return Err(format!("Unsupported format: {:?}", format)),
+        None => return Err("Unknown format".into()),
+    }
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(max_dimension);
+    limits.max_image_height = Some(max_dimension);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|err| err.to_string())?;
+
+    let longest = image.width().max(image.height());
+    let mut sizes = sizes.to_vec();
+    sizes.sort_unstable();
+    sizes.dedup();
+    let mut thumbnails = Vec::new();
+    for size in sizes.into_iter().filter(|&size| size < longest) {
+        let thumbnail = image.thumbnail(size, size);
+        let mut png = Vec::new();
+        thumbnail
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|err| err.to_string())?;
+        thumbnails.push((thumbnail.width(), thumbnail.height(), png));
+    }
+
+    Ok(Decoded {
+        width: image.width(),
+        height: image.height(),
+        thumbnails,
+    })
+}
+
+fn failed(code: ErrorCode) -> MessageReply {
+    MessageReply::failed(code, None)
+}
@@ -0,0 +1,181 @@
+use guard::guard;
+
+use crate::{
+    frame::messages::Inbound,
+    message::{
+        ClientMessage, Content, DownloadChunk, DownloadRequest, ErrorCode, MessageReply,
+        ScheduleMessage, UploadChunk, UploadReply, UploadRequest,
+    },
+};
+
+use super::{blob_store::Progress, thumbnail, BlobMeta, BlobStore, Client, Config};
+
+/// Upper bound of chunk data, keeps a single chunk from hogging the connection.
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Max length of chunk data, leaving room in the payload for base64 and the other fields.
+pub fn chunk_size(config: &Config) -> usize {
+    (config.max_payload_length / 2).min(MAX_CHUNK_SIZE)
+}
+
+/// Handles transfer frames and checks the blobs messages refer to, handing any other payload back.
+pub async fn handle(
+    msg: Inbound,
+    client: &Client,
+    blobs: &BlobStore,
+    config: &Config,
+) -> Option<Inbound> {
+    match msg {
+        Inbound::UploadRequest(request) => upload(request, client, blobs, config).await,
+        Inbound::UploadChunk(chunk) => upload_chunk(chunk, client, blobs, config).await,
+        Inbound::DownloadRequest(request) => download(request, client, blobs, config).await,
+        Inbound::ClientMessage(message) => {
+            return share(message, client, blobs, config)
+                .await
+                .map(Inbound::ClientMessage)
+        }
+        // Checked like a message sent now, the receiver is let in once it's sent.
+        Inbound::ScheduleMessage(schedule) => {
+            let ScheduleMessage {
+                deliver_at,
+                message,
+            } = schedule;
+            let message = share(message, client, blobs, config).await?;
+            return Some(Inbound::ScheduleMessage(ScheduleMessage {
+                deliver_at,
+                message,
+            }));
+        }
+        msg => return Some(msg),
+    }
+    None
+}
+
+async fn upload(request: UploadRequest, client: &Client, blobs: &BlobStore, config: &Config) {
+    let transfer_id = request.transfer_id.clone();
+    let result = if request.size > config.max_blob_size {
+        Err(ErrorCode::TooLarge)
+    } else {
+        blobs.start_upload(&client.uid, request).await
+    };
+    let reply = match result {
+        Ok(progress) => progress_reply(progress, config),
+        Err(code) => UploadReply::failed(transfer_id, code),
+    };
+    client.send(reply).await
+}
+
+async fn upload_chunk(chunk: UploadChunk, client: &Client, blobs: &BlobStore, config: &Config) {
+    let transfer_id = chunk.transfer_id.clone();
+    let reply = if chunk.data.len() > chunk_size(config) {
+        UploadReply::failed(Some(transfer_id), ErrorCode::TooLarge)
+    } else {
+        match blobs.write_chunk(&client.uid, chunk).await {
+            Ok(progress) => progress_reply(progress, config),
+            Err(code) => UploadReply::failed(Some(transfer_id), code),
+        }
+    };
+    client.send(reply).await
+}
+
+async fn download(request: DownloadRequest, client: &Client, blobs: &BlobStore, config: &Config) {
+    let DownloadRequest { blob_id, offset } = request;
+    let chunk = match blobs
+        .read_chunk(&client.uid, &blob_id, offset, chunk_size(config))
+        .await
+    {
+        Ok((data, size)) => DownloadChunk::new(blob_id, offset, size, data),
+        Err(code) => DownloadChunk::failed(blob_id, code),
+    };
+    client.send(chunk).await
+}
+
+fn progress_reply(progress: Progress, config: &Config) -> UploadReply {
+    let mut reply = UploadReply::success(progress.transfer_id, progress.offset, chunk_size(config));
+    reply.url = progress
+        .blob_id
+        .as_ref()
+        .and_then(|blob_id| config.blob_url(blob_id));
+    reply.blob_id = progress.blob_id;
+    reply
+}
+
+// Sharing
+
+/// Checks the blob a message refers to is one the sender may read.
+///
+/// The receiver is only let in by `grant`, once the message is accepted.
+#[allow(clippy::diverging_sub_expression)]
+async fn share(
+    mut message: ClientMessage,
+    client: &Client,
+    blobs: &BlobStore,
+    config: &Config,
+) -> Option<ClientMessage> {
+    let blob_id = referenced_blob(&message.content, config).map(str::to_string);
+    guard!(let Some(blob_id) = blob_id else { return Some(message) });
+    let shared = match blobs.meta(&client.uid, &blob_id).await {
+        Ok(meta) => match check_file(&message.content, &meta) {
+            Ok(()) => {
+                let (sender, content) = (&client.uid, &mut message.content);
+                thumbnail::attach(content, &blob_id, sender, blobs, config).await
+            }
+            Err(reply) => Err(reply),
+        },
+        Err(code) => Err(MessageReply::failed(code, None)),
+    };
+    match shared {
+        Ok(()) => Some(message),
+        Err(reply) => {
+            client.send(reply).await;
+            None
+        }
+    }
+}
+
+/// A file has to be declared as the blob was uploaded.
+fn check_file(content: &Content, meta: &BlobMeta) -> Result<(), MessageReply> {
+    let Content::File { size, mime, .. } = content else {
+        return Ok(());
+    };
+    if *size == meta.size && *mime == meta.mime {
+        return Ok(());
+    }
+    let message = format!(
+        "Declared {} bytes of {}, actually {} bytes of {}",
+        size, mime, meta.size, meta.mime
+    );
+    Err(MessageReply::failed(
+        ErrorCode::MalformedPayload,
+        Some(message),
+    ))
+}
+
+/// Lets the receiver read the blob an accepted message refers to, and both sides its thumbnails.
+pub async fn grant(
+    content: &Content,
+    sender: &str,
+    receiver: &str,
+    blobs: &BlobStore,
+    config: &Config,
+) -> Result<(), ErrorCode> {
+    let Some(blob_id) = referenced_blob(content, config) else {
+        return Ok(());
+    };
+    blobs.grant(sender, blob_id, receiver).await?;
+    if let Content::Image { .. } = content {
+        thumbnail::grant(blob_id, sender, receiver, blobs).await?;
+    }
+    Ok(())
+}
+
+fn referenced_blob<'a>(content: &'a Content, config: &Config) -> Option<&'a str> {
+    match content {
+        Content::File { blob_id, .. } => Some(blob_id),
+        // Images hosted elsewhere are none of our business.
+        Content::Image { url, .. } => url
+            .strip_prefix(config.public_url.as_deref()?)?
+            .strip_prefix("/blobs/"),
+        _ => None,
+    }
+}
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::time::Instant;
+
+use crate::message::TypingState;
+
+/// Senders currently typing, keyed by sender then receiver.
+#[derive(Debug)]
+pub struct Typists {
+    throttle: Duration,
+    timeout: Duration,
+    active: HashMap<(String, String), Typist>,
+}
+
+#[derive(Debug)]
+struct Typist {
+    forwarded_at: Instant,
+    expires_at: Instant,
+}
+
+impl Typists {
+    pub fn new(throttle: Duration, timeout: Duration) -> Self {
+        Self {
+            throttle,
+            timeout,
+            active: HashMap::new(),
+        }
+    }
+
+    /// Records `state`, returns whether it's worth forwarding to `receiver`.
+    pub fn update(&mut self, sender: &str, receiver: &str, state: TypingState) -> bool {
+        let key = (sender.to_string(), receiver.to_string());
+        let now = Instant::now();
+        match state {
+            TypingState::Typing => match self.active.get_mut(&key) {
+                Some(typist) => {
+                    typist.expires_at = now + self.timeout;
+                    if now < typist.forwarded_at + self.throttle {
+                        return false;
+                    }
+                    typist.forwarded_at = now;
+                    true
+                }
+                None => {
+                    let typist = Typist {
+                        forwarded_at: now,
+                        expires_at: now + self.timeout,
+                    };
+                    self.active.insert(key, typist);
+                    true
+                }
+            },
+            // Only the receiver of a `Typing` needs to hear it stopped.
+            TypingState::Stopped => self.active.remove(&key).is_some(),
+        }
+    }
+
+    /// Forgets `sender` typing to `receiver` without anything to forward,
+    /// e.g. once the message arrives.
+    pub fn clear(&mut self, sender: &str, receiver: &str) {
+        self.active
+            .remove(&(sender.to_string(), receiver.to_string()));
+    }
+
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.active.values().map(|typist| typist.expires_at).min()
+    }
+
+    /// Removes the timed out, returns them as (sender, receiver).
+    pub fn expire(&mut self) -> Vec<(String, String)> {
+        let now = Instant::now();
+        let expired: Vec<_> = self
+            .active
+            .iter()
+            .filter(|(_, typist)| typist.expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.active.remove(key);
+        }
+        expired
+    }
+}
@@ -1,18 +1,68 @@
+use std::{future::Future, net::SocketAddr};
+
+use futures::future;
 use log::{LevelFilter, Metadata, Record, SetLoggerError};
+use tokio::{net::TcpListener, select};
 
 pub mod frame;
 pub mod handler;
 pub mod message;
 
-pub async fn run_server(addr: impl tokio::net::ToSocketAddrs) -> anyhow::Result<()> {
+/// Serves chat on `addr`, and blobs over HTTP on `http_addr` unless it's `None`.
+pub async fn run_server(
+    addr: impl tokio::net::ToSocketAddrs,
+    http_addr: Option<SocketAddr>,
+    public_url: Option<String>,
+) -> anyhow::Result<()> {
     let _ = Logger::init();
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    let handler = handler::Handler::run();
+    let listener = TcpListener::bind(addr).await?;
+    let config = handler::Config {
+        history_path: Some("history.jsonl".into()),
+        schedule_path: Some("schedule.json".into()),
+        http_addr,
+        public_url,
+        ..Default::default()
+    };
+    serve(listener, config).await
+}
+
+pub async fn serve(listener: TcpListener, config: handler::Config) -> anyhow::Result<()> {
+    serve_until(listener, config, future::pending()).await
+}
+
+/// Serves until `shutdown` resolves, then closes every connection and finishes pending writes.
+#[allow(clippy::diverging_sub_expression)]
+pub async fn serve_until(
+    listener: TcpListener,
+    mut config: handler::Config,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let http = match config.http_addr {
+        Some(addr) => Some(TcpListener::bind(addr).await?),
+        None => None,
+    };
+    if let Some(http) = &http {
+        let addr = http.local_addr()?;
+        config
+            .public_url
+            .get_or_insert_with(|| format!("http://{}", addr));
+    }
+    let mut handler = handler::Handler::run_with_config(config);
+    if let Some(http) = http {
+        handler.serve_http(http);
+    }
 
+    tokio::pin!(shutdown);
     loop {
-        guard::guard!(let Ok((stream, _addr)) = listener.accept().await else { continue });
-        handler.connect(stream);
+        let accepted = select! {
+            _ = &mut shutdown => break,
+            accepted = listener.accept() => accepted,
+        };
+        guard::guard!(let Ok((stream, addr)) = accepted else { continue });
+        handler.connect(stream, addr);
     }
+    handler.shutdown().await;
+    Ok(())
 }
 
 // Logger
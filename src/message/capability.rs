@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+/// Optional protocol features, advertised by both sides in the handshake.
+///
+/// The server only pushes events of a capability both sides listed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Deflate compressed frames, negotiated by `compressions`.
+    Compression,
+    /// MessagePack payloads, negotiated by `encodings`.
+    MessagePack,
+    /// Chunked uploads and downloads of files.
+    Blobs,
+    /// Thumbnails attached to image messages.
+    Thumbnails,
+    /// Persisted history, edits and recalls.
+    History,
+    /// `Receipt` events for delivered and read messages.
+    Receipts,
+    /// `PeerTyping` events.
+    Typing,
+    /// `ReactionUpdated` events.
+    Reactions,
+    /// Quoted replies and threads.
+    Replies,
+    /// Messages with a TTL, and `MessageExpired` events.
+    Ephemeral,
+    /// Messages scheduled for later delivery.
+    Scheduling,
+    /// Location, markdown and mention content.
+    RichContent,
+    /// `Mention` events.
+    Mentions,
+    /// A capability introduced by a newer peer.
+    #[serde(other)]
+    Unknown,
+}
+
+impl Capability {
+    /// Capabilities this server implements.
+    pub const SUPPORTED: &'static [Capability] = &[
+        Self::Compression,
+        Self::MessagePack,
+        Self::Blobs,
+        Self::Thumbnails,
+        Self::History,
+        Self::Receipts,
+        Self::Typing,
+        Self::Reactions,
+        Self::Replies,
+        Self::Ephemeral,
+        Self::Scheduling,
+        Self::RichContent,
+        Self::Mentions,
+    ];
+
+    /// The supported capabilities the peer listed too, in the server's order.
+    pub fn negotiate(offered: &[Capability]) -> Vec<Capability> {
+        Self::SUPPORTED
+            .iter()
+            .filter(|capability| offered.contains(capability))
+            .copied()
+            .collect()
+    }
+}
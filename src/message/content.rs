@@ -1,6 +1,9 @@
 use std::fmt::Display;
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use super::markdown;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type", content = "content", rename_all = "snake_case")]
@@ -10,15 +13,170 @@ pub enum Content {
         url: String,
         width: f64,
         height: f64,
+        /// Previews generated by the server for images it stores, smallest first.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        thumbnails: Vec<Thumbnail>,
+    },
+    /// A blob uploaded to the server, see [`UploadRequest`](super::UploadRequest).
+    File {
+        blob_id: String,
+        name: String,
+        size: u64,
+        mime: String,
+    },
+    Location {
+        latitude: f64,
+        longitude: f64,
+        /// What's there, e.g. a place or an address.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+    /// Formatted text in the subset of markdown described in `markdown.rs`.
+    Markdown(String),
+    /// Text with `@mentions` of other users.
+    Mentions {
+        text: String,
+        /// In order of appearance.
+        mentions: Vec<MentionSpan>,
     },
+    /// Content of a type introduced by a newer peer, kept as is so it can be relayed.
+    #[serde(untagged)]
+    Unknown {
+        #[serde(deserialize_with = "deserialize_unknown_type")]
+        r#type: String,
+        #[serde(rename = "content", default)]
+        raw: Value,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Thumbnail {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A mention in the text of [`Content::Mentions`].
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct MentionSpan {
+    /// Uid of the mentioned user.
+    pub user: String,
+    /// Byte range of the mention in the text, starting with `@`.
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Content {
+    /// Every `type` tag this version understands.
+    pub const KNOWN_TYPES: &'static [&'static str] =
+        &["text", "image", "file", "location", "markdown", "mentions"];
+
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, Self::Unknown { .. })
+    }
+
+    /// Whether `uid` is mentioned.
+    pub fn mentions(&self, uid: &str) -> bool {
+        match self {
+            Self::Mentions { mentions, .. } => mentions.iter().any(|mention| mention.user == uid),
+            _ => false,
+        }
+    }
+
+    /// Checks what serde can't, describing the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Text(_) | Self::Unknown { .. } => Ok(()),
+            Self::Image {
+                url, width, height, ..
+            } => ensure(
+                !url.is_empty() && is_length(*width) && is_length(*height),
+                || format!("Invalid image: {}x{} at {:?}", width, height, url),
+            ),
+            Self::File {
+                blob_id,
+                name,
+                mime,
+                ..
+            } => ensure(
+                !blob_id.is_empty() && !name.is_empty() && mime.contains('/'),
+                || format!("Invalid file: {:?} ({}) of {:?}", name, mime, blob_id),
+            ),
+            Self::Location {
+                latitude,
+                longitude,
+                ..
+            } => ensure(
+                (-90.0..=90.0).contains(latitude) && (-180.0..=180.0).contains(longitude),
+                || format!("Invalid location: {}, {}", latitude, longitude),
+            ),
+            Self::Markdown(text) => markdown::validate(text),
+            Self::Mentions { text, mentions } => validate_mentions(text, mentions),
+        }
+    }
+}
+
+fn ensure(valid: bool, describe: impl FnOnce() -> String) -> Result<(), String> {
+    if valid {
+        Ok(())
+    } else {
+        Err(describe())
+    }
+}
+
+fn is_length(length: f64) -> bool {
+    length.is_finite() && length > 0.0
+}
+
+/// Mentions have to point at `@`s of the text in order, without overlapping.
+fn validate_mentions(text: &str, mentions: &[MentionSpan]) -> Result<(), String> {
+    let mut last_end = 0;
+    for MentionSpan { user, start, end } in mentions {
+        let span = text.get(*start..*end).filter(|_| *start >= last_end);
+        if user.is_empty() || !span.is_some_and(|span| span.starts_with('@')) {
+            return Err(format!(
+                "Misplaced mention of {:?} at {}..{}",
+                user, start, end
+            ));
+        }
+        last_end = *end;
+    }
+    Ok(())
+}
+
+/// Known types with malformed content must fail rather than fall back to `Unknown`.
+fn deserialize_unknown_type<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let r#type = String::deserialize(deserializer)?;
+    if Content::KNOWN_TYPES.contains(&r#type.as_str()) {
+        Err(de::Error::custom(format!(
+            "malformed content of type {}",
+            r#type
+        )))
+    } else {
+        Ok(r#type)
+    }
 }
 
 impl Display for Content {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let str = match self {
-            Self::Text(text) => text,
-            Self::Image { url, .. } => url,
-        };
-        f.write_str(str)
+        match self {
+            Self::Text(text) => f.write_str(text),
+            Self::Image { url, .. } => f.write_str(url),
+            Self::File { name, .. } => write!(f, "[File: {}]", name),
+            Self::Location {
+                name: Some(name), ..
+            } => write!(f, "[Location: {}]", name),
+            Self::Location {
+                latitude,
+                longitude,
+                ..
+            } => write!(f, "[Location: {}, {}]", latitude, longitude),
+            Self::Markdown(text) => f.write_str(&markdown::to_plain(text)),
+            Self::Mentions { text, .. } => f.write_str(text),
+            Self::Unknown { r#type, .. } => write!(f, "[Unsupported content: {}]", r#type),
+        }
     }
 }
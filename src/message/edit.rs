@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Payload;
+
+use super::Content;
+
+/// Replaces the content of a message the client sent.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x03, direction = client_to_server)]
+pub struct EditMessage {
+    pub id: u64,
+    pub content: Content,
+}
+
+/// Recalls a message the client sent, within the server's recall window.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x04, direction = client_to_server)]
+pub struct DeleteMessage {
+    pub id: u64,
+}
+
+/// Pushed to both sides of a conversation once a message is edited.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x03, direction = server_to_client)]
+pub struct MessageEdited {
+    pub id: u64,
+    pub content: Content,
+    pub edited_at: u64,
+}
+
+/// Pushed to both sides of a conversation once a message is deleted.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x04, direction = server_to_client)]
+pub struct MessageDeleted {
+    pub id: u64,
+}
+
+/// Pushed to both sides of a conversation once a message outlives its TTL.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x0A, direction = server_to_client)]
+pub struct MessageExpired {
+    pub id: u64,
+}
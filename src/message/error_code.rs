@@ -0,0 +1,68 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::frame;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    AuthFailed,
+    UnsupportedVersion,
+    UserExists,
+    ReceiverNotFound,
+    MalformedPayload,
+    TypeMismatch,
+    TooLarge,
+    TransferNotFound,
+    BlobNotFound,
+    OffsetMismatch,
+    ChecksumMismatch,
+    InvalidImage,
+    MessageNotFound,
+    Forbidden,
+    RecallExpired,
+    Timeout,
+    Internal,
+    /// A code introduced by a newer peer.
+    #[serde(other)]
+    Unknown,
+}
+
+impl From<&frame::Error> for ErrorCode {
+    fn from(err: &frame::Error) -> Self {
+        match err {
+            frame::Error::Io(_) => Self::Internal,
+            frame::Error::TypeMismatch(_) | frame::Error::WrongDirection(_) => Self::TypeMismatch,
+            frame::Error::TooLarge(_) => Self::TooLarge,
+            frame::Error::Compression(_) | frame::Error::Coding(_) => Self::MalformedPayload,
+            frame::Error::SlowFrame => Self::Timeout,
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            Self::AuthFailed => "Authentication failed",
+            Self::UnsupportedVersion => "Unsupported protocol version",
+            Self::UserExists => "User existed",
+            Self::ReceiverNotFound => "Receiver not found",
+            Self::MalformedPayload => "Malformed payload",
+            Self::TypeMismatch => "Type mismatch",
+            Self::TooLarge => "Too large",
+            Self::TransferNotFound => "Transfer not found",
+            Self::BlobNotFound => "Blob not found",
+            Self::OffsetMismatch => "Offset mismatch",
+            Self::ChecksumMismatch => "Checksum mismatch",
+            Self::InvalidImage => "Invalid image",
+            Self::MessageNotFound => "Message not found",
+            Self::Forbidden => "Forbidden",
+            Self::RecallExpired => "Recall window expired",
+            Self::Timeout => "Timed out",
+            Self::Internal => "Internal error",
+            Self::Unknown => "Unknown error",
+        };
+        f.write_str(str)
+    }
+}
@@ -1,36 +1,137 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use crate::frame::{self, Compression, Encoding, Payload};
+
+use super::{Capability, ErrorCode};
+
+/// The protocol version this crate speaks.
+pub const PROTOCOL_VERSION: u32 = 2;
+/// The oldest protocol version still accepted, clients sending no version speak 1.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x00, direction = client_to_server)]
 pub struct Handshake {
     pub token: String,
+    /// The newest protocol version the client speaks.
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub client: Option<ClientInfo>,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    /// Encodings the client accepts for the rest of the connection, by preference.
+    #[serde(default)]
+    pub encodings: Vec<Encoding>,
+    /// Compressions the client accepts for the rest of the connection, by preference.
+    #[serde(default)]
+    pub compressions: Vec<Compression>,
 }
 
 impl Handshake {
     pub fn new(token: String) -> Self {
-        Self { token }
+        Self {
+            token,
+            version: PROTOCOL_VERSION,
+            client: None,
+            capabilities: Capability::SUPPORTED.to_vec(),
+            encodings: Vec::new(),
+            compressions: Vec::new(),
+        }
+    }
+
+    /// The version both sides speak, `None` if the client is too old.
+    pub fn negotiate_version(&self) -> Option<u32> {
+        Some(self.version.min(PROTOCOL_VERSION)).filter(|&v| v >= MIN_PROTOCOL_VERSION)
+    }
+}
+
+fn legacy_version() -> u32 {
+    1
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ClientInfo {
+    pub name: String,
+    pub version: String,
+}
+
+impl ClientInfo {
+    pub fn new(name: String, version: String) -> Self {
+        Self { name, version }
     }
 }
 
+/// Limits the server enforces on the connection.
 #[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Limits {
+    /// Max payload length of a frame, before compression.
+    pub max_payload_length: usize,
+    /// Rates of inbound frames per user, the default one first.
+    #[serde(default)]
+    pub rate_limits: Vec<RateLimit>,
+}
+
+/// A token bucket of inbound frames, see [`RateLimited`](super::RateLimited).
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// The type code limited, `None` for types without a rate of their own.
+    pub type_code: Option<u8>,
+    /// Frames in a full bucket.
+    pub burst: u32,
+    /// Frames added back each second.
+    pub per_second: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x00, direction = server_to_client)]
 pub struct HandshakeReply {
     pub success: bool,
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
     pub message: Option<String>,
+    /// The negotiated protocol version, or the server's newest one on failure.
+    #[serde(default = "legacy_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub capabilities: Vec<Capability>,
+    #[serde(default)]
+    pub limits: Option<Limits>,
+    /// The negotiated encoding, applies to every frame after this reply.
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// The negotiated compression, frames after this reply may be compressed with it.
+    #[serde(default)]
+    pub compression: Option<Compression>,
+    /// Bearer token of the HTTP endpoint, valid until the connection closes.
+    #[serde(default)]
+    pub http_token: Option<String>,
 }
 
 impl HandshakeReply {
-    pub fn new(success: bool, message: Option<String>) -> Self {
-        Self { success, message }
+    pub fn new(success: bool, code: Option<ErrorCode>, message: Option<String>) -> Self {
+        Self {
+            success,
+            code,
+            message,
+            version: PROTOCOL_VERSION,
+            capabilities: Vec::new(),
+            limits: None,
+            encoding: Encoding::default(),
+            compression: None,
+            http_token: None,
+        }
     }
 
     pub fn success(message: Option<String>) -> Self {
-        Self::new(true, message)
+        Self::new(true, None, message)
     }
 
-    pub fn failed(message: Option<String>) -> Self {
-        Self::new(false, message)
+    pub fn failed(code: ErrorCode, message: Option<String>) -> Self {
+        Self::new(false, Some(code), message)
     }
 
-    pub fn error(err: impl std::error::Error) -> Self {
-        Self::failed(Some(err.to_string()))
+    pub fn error(err: frame::Error) -> Self {
+        Self::failed((&err).into(), Some(err.to_string()))
     }
 }
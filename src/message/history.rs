@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Payload;
+
+use super::ServerMessage;
+
+/// Asks for the latest messages exchanged with `peer`, older than `before` if set.
+///
+/// With `thread`, only replies in the thread started by that message are returned.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x05, direction = client_to_server)]
+pub struct HistoryRequest {
+    pub peer: String,
+    #[serde(default)]
+    pub before: Option<u64>,
+    pub limit: usize,
+    #[serde(default)]
+    pub thread: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x05, direction = server_to_client)]
+pub struct HistoryReply {
+    pub peer: String,
+    /// Oldest first, in their latest state.
+    pub messages: Vec<ServerMessage>,
+    /// Id of the last message from `peer` the client has read.
+    #[serde(default)]
+    pub read_up_to: u64,
+    /// Id of the last message to `peer` they have read.
+    #[serde(default)]
+    pub peer_read_up_to: u64,
+    /// Messages from `peer` after `read_up_to`.
+    #[serde(default)]
+    pub unread: usize,
+    /// Those of the unread messages which mention the client.
+    #[serde(default)]
+    pub unread_mentions: usize,
+    /// Whether there are older messages, to be asked for with `before` the oldest one
+    /// here. Fewer than `limit` come with it when more wouldn't fit in a payload.
+    #[serde(default)]
+    pub has_more: bool,
+}
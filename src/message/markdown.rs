@@ -0,0 +1,118 @@
+//
+// The markdown subset of `Content::Markdown`:
+//
+// ```            Fenced code blocks, kept verbatim until the closing fence.
+// `code`         Inline code.
+// **bold**       Bold, and *italic*, both closed on the same line.
+// [text](url)    Links to http, https or mailto URLs.
+// \*             Escapes the next character.
+//
+// Lines may also start with `#` headings, `-` items or `>` quotes, which need no
+// closing and are shown as they are.
+//
+
+const FENCE: &str = "```";
+const LINK_SCHEMES: &[&str] = &["http://", "https://", "mailto:"];
+
+/// Checks that every construct of `text` is closed and every link is allowed.
+pub fn validate(text: &str) -> Result<(), String> {
+    render(text).map(|_| ())
+}
+
+/// `text` without markup, for receivers which don't render markdown.
+pub fn to_plain(text: &str) -> String {
+    // Invalid markdown never gets past the server.
+    render(text).unwrap_or_else(|_| text.to_string())
+}
+
+fn render(text: &str) -> Result<String, String> {
+    let mut plain = Vec::new();
+    let mut in_fence = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with(FENCE) {
+            in_fence = !in_fence;
+        } else if in_fence {
+            plain.push(line.to_string());
+        } else {
+            plain.push(render_line(line)?);
+        }
+    }
+    if in_fence {
+        return Err("Unclosed code block".into());
+    }
+    Ok(plain.join("\n"))
+}
+
+fn render_line(line: &str) -> Result<String, String> {
+    let line = match line.trim_start_matches('#') {
+        heading if heading.len() < line.len() && heading.starts_with(' ') => heading.trim_start(),
+        _ => line,
+    };
+    let mut plain = String::new();
+    let (mut bold, mut italic) = (false, false);
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        rest = &rest[c.len_utf8()..];
+        match c {
+            '\\' => match rest.chars().next() {
+                Some(escaped) => {
+                    plain.push(escaped);
+                    rest = &rest[escaped.len_utf8()..];
+                }
+                None => plain.push(c),
+            },
+            '`' => {
+                let end = rest.find('`').ok_or("Unclosed `code`")?;
+                plain.push_str(&rest[..end]);
+                rest = &rest[end + 1..];
+            }
+            '*' if rest.starts_with('*') && toggles(bold, before(line, rest), &rest[1..]) => {
+                bold = !bold;
+                rest = &rest[1..];
+            }
+            '*' if toggles(italic, before(line, rest), rest) => italic = !italic,
+            '[' => match parse_link(rest)? {
+                Some((text, url, after)) => {
+                    plain.push_str(&format!("{} ({})", text, url));
+                    rest = after;
+                }
+                None => plain.push(c),
+            },
+            c => plain.push(c),
+        }
+    }
+    match (bold, italic) {
+        (true, _) => Err("Unclosed **".into()),
+        (_, true) => Err("Unclosed *".into()),
+        _ => Ok(plain),
+    }
+}
+
+/// Whether a `*` run closes the emphasis if `open`, or opens one otherwise.
+///
+/// Like in CommonMark, openers are followed and closers preceded by non-whitespace,
+/// so `2 * 3` or a `* ` bullet stay as they are.
+fn toggles(open: bool, before: Option<char>, after: &str) -> bool {
+    let neighbour = if open { before } else { after.chars().next() };
+    neighbour.is_some_and(|c| !c.is_whitespace())
+}
+
+/// The character before the `*` run whose first `*` was just taken off `rest`.
+fn before(line: &str, rest: &str) -> Option<char> {
+    line[..line.len() - rest.len() - 1].chars().next_back()
+}
+
+/// Parses `text](url)` right after a `[`, `None` if the brackets aren't a link.
+fn parse_link(rest: &str) -> Result<Option<(&str, &str, &str)>, String> {
+    let close = match rest.find("](") {
+        Some(close) if !rest[..close].contains(']') => close,
+        _ => return Ok(None),
+    };
+    let after_text = &rest[close + 2..];
+    let end = after_text.find(')').ok_or("Malformed link")?;
+    let url = &after_text[..end];
+    if !LINK_SCHEMES.iter().any(|scheme| url.starts_with(scheme)) {
+        return Err(format!("Unsupported link: {}", url));
+    }
+    Ok(Some((&rest[..close], url, &after_text[end + 1..])))
+}
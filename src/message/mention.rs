@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Payload;
+
+/// Pushed to a user mentioned in a message, next to the message itself.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x0E, direction = server_to_client)]
+pub struct Mention {
+    /// Id of the message.
+    pub id: u64,
+    pub sender: String,
+    /// Mentions from `sender` the user hasn't read yet, this one included.
+    pub unread_mentions: usize,
+}
@@ -1,14 +1,55 @@
+mod capability;
+pub use self::capability::Capability;
+
 mod content;
-pub use self::content::Content;
+pub use self::content::{Content, MentionSpan, Thumbnail};
+
+mod markdown;
+
+mod error_code;
+pub use self::error_code::ErrorCode;
 
 mod handshake;
-pub use self::handshake::{Handshake, HandshakeReply};
+pub use self::handshake::{
+    ClientInfo, Handshake, HandshakeReply, Limits, RateLimit, MIN_PROTOCOL_VERSION,
+    PROTOCOL_VERSION,
+};
 
 mod normal;
+pub(crate) use self::normal::now_millis;
 pub use self::normal::{ClientMessage, ServerMessage};
 
+mod edit;
+pub use self::edit::{DeleteMessage, EditMessage, MessageDeleted, MessageEdited, MessageExpired};
+
+mod history;
+pub use self::history::{HistoryReply, HistoryRequest};
+
+mod mention;
+pub use self::mention::Mention;
+
+mod rate_limited;
+pub use self::rate_limited::RateLimited;
+
+mod reaction;
+pub use self::reaction::{AddReaction, Reaction, ReactionUpdated, RemoveReaction};
+
+mod receipt;
+pub use self::receipt::{Acknowledge, Receipt, ReceiptKind};
+
+mod schedule;
+pub use self::schedule::{
+    CancelScheduled, ListScheduled, ScheduleMessage, ScheduledList, ScheduledMessage,
+};
+
+mod typing;
+pub use self::typing::{PeerTyping, Typing, TypingState};
+
 mod reply;
 pub use self::reply::MessageReply;
 
+mod transfer;
+pub use self::transfer::{DownloadChunk, DownloadRequest, UploadChunk, UploadReply, UploadRequest};
+
 mod ping_pong;
 pub use self::ping_pong::{Ping, Pong};
@@ -1,33 +1,142 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use guard::guard;
 use serde::{Deserialize, Serialize};
 
-use super::Content;
+use crate::frame::Payload;
+
+use super::{Content, Reaction};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x01, direction = client_to_server)]
 pub struct ClientMessage {
     pub content: Content,
     pub receiver: String,
+    /// Plain text shown by receivers which don't understand `content`.
+    #[serde(default)]
+    pub fallback: Option<String>,
+    /// Id of an earlier message of the same conversation this one replies to.
+    #[serde(default)]
+    pub reply_to: Option<u64>,
+    /// Milliseconds after which the message is deleted for everyone, `None` keeps it.
+    #[serde(default)]
+    pub ttl: Option<u64>,
 }
 
 impl ClientMessage {
     pub fn new(content: Content, receiver: String) -> Self {
-        Self { content, receiver }
+        Self {
+            content,
+            receiver,
+            fallback: None,
+            reply_to: None,
+            ttl: None,
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x01, direction = server_to_client)]
 pub struct ServerMessage {
+    /// Assigned by the server, unique across conversations.
+    #[serde(default)]
+    pub id: u64,
+    /// When the server accepted the message, in milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub timestamp: u64,
+    /// When the content was last edited, `None` if it never was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<u64>,
+    /// When the message expires, in milliseconds since the Unix epoch, `None` if it doesn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
     #[serde(flatten)]
     pub content: Content,
     pub sender: String,
     pub receiver: String,
+    /// Plain text supplied by the sender for receivers which don't understand `content`.
+    #[serde(default)]
+    pub fallback: Option<String>,
+    /// Id of the message this one replies to, which may since have been deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<u64>,
+    /// Ordered by the first reaction with each emoji.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reactions: Vec<Reaction>,
 }
 
 impl ServerMessage {
     pub fn new(content: Content, sender: String, receiver: String) -> Self {
         Self {
+            id: 0,
+            timestamp: now_millis(),
+            edited_at: None,
+            expires_at: None,
             content,
             sender,
             receiver,
+            fallback: None,
+            reply_to: None,
+            reactions: Vec::new(),
+        }
+    }
+
+    /// Adds `uid`'s reaction, returns whether it's new.
+    pub fn add_reaction(&mut self, emoji: &str, uid: &str) -> bool {
+        match self.reactions.iter_mut().find(|r| r.emoji == emoji) {
+            Some(reaction) if reaction.users.iter().any(|user| user == uid) => return false,
+            Some(reaction) => reaction.users.push(uid.into()),
+            None => self.reactions.push(Reaction {
+                emoji: emoji.into(),
+                count: 0,
+                users: vec![uid.into()],
+            }),
+        }
+        self.count_reactions();
+        true
+    }
+
+    /// Removes `uid`'s reaction, returns whether there was one.
+    #[allow(clippy::diverging_sub_expression)]
+    pub fn remove_reaction(&mut self, emoji: &str, uid: &str) -> bool {
+        let reaction = self.reactions.iter_mut().find(|r| r.emoji == emoji);
+        guard!(let Some(reaction) = reaction else { return false });
+        let count = reaction.users.len();
+        reaction.users.retain(|user| user != uid);
+        if reaction.users.len() == count {
+            return false;
         }
+        self.count_reactions();
+        true
     }
+
+    /// Whether the message belongs to the conversation between `a` and `b`.
+    pub fn is_between(&self, a: &str, b: &str) -> bool {
+        (self.sender == a && self.receiver == b) || (self.sender == b && self.receiver == a)
+    }
+
+    /// The content as text, preferring the sender's fallback for unknown content.
+    pub fn display_content(&self) -> String {
+        match (&self.content, &self.fallback) {
+            (Content::Unknown { .. }, Some(fallback)) => fallback.clone(),
+            (content, _) => content.to_string(),
+        }
+    }
+}
+
+impl ServerMessage {
+    fn count_reactions(&mut self) {
+        for reaction in &mut self.reactions {
+            reaction.count = reaction.users.len();
+        }
+        self.reactions.retain(|reaction| reaction.count > 0);
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
 }
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+use crate::frame::Payload;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Payload)]
+#[payload(code = 0xFF, direction = client_to_server)]
 pub struct Ping;
 
 impl Ping {
@@ -15,7 +18,8 @@ impl Default for Ping {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Payload)]
+#[payload(code = 0xFF, direction = server_to_client)]
 pub struct Pong;
 
 impl Pong {
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Payload;
+
+/// Sent instead of handling a frame which went over the rate limits.
+///
+/// Connections which keep going over them are closed.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x0F, direction = server_to_client)]
+pub struct RateLimited {
+    /// Type code of the dropped frame, `None` if it couldn't be decoded.
+    pub type_code: Option<u8>,
+    /// Milliseconds until a frame of the type is let through again.
+    pub retry_after: u64,
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Payload;
+
+/// Reacts to a message of the client's conversations with `emoji`.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x08, direction = client_to_server)]
+pub struct AddReaction {
+    pub id: u64,
+    pub emoji: String,
+}
+
+/// Takes back the client's `emoji` reaction to a message.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x09, direction = client_to_server)]
+pub struct RemoveReaction {
+    pub id: u64,
+    pub emoji: String,
+}
+
+/// Pushed to both sides of a conversation once the reactions to a message change.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x08, direction = server_to_client)]
+pub struct ReactionUpdated {
+    pub id: u64,
+    /// Every reaction to the message after the change.
+    pub reactions: Vec<Reaction>,
+}
+
+/// Everyone who reacted to a message with the same emoji.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Reaction {
+    pub emoji: String,
+    pub count: usize,
+    /// In the order they reacted.
+    pub users: Vec<String>,
+}
+
+impl Reaction {
+    /// Longest emoji accepted, in bytes, enough for ZWJ sequences.
+    pub const MAX_EMOJI_LENGTH: usize = 32;
+
+    /// Whether `emoji` looks like an emoji rather than text.
+    pub fn is_valid_emoji(emoji: &str) -> bool {
+        !emoji.is_empty()
+            && emoji.len() <= Self::MAX_EMOJI_LENGTH
+            // Keycaps start with ASCII, but nothing is ASCII only.
+            && !emoji.is_ascii()
+            && !emoji.chars().any(|c| c.is_whitespace() || c.is_control())
+    }
+}
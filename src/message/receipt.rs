@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Payload;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptKind {
+    /// The message reached the receiver's client.
+    Delivered,
+    /// The receiver has seen every message up to this one.
+    Read,
+}
+
+/// Acknowledges a message the client received.
+///
+/// Only failures are answered with a `MessageReply`.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x06, direction = client_to_server)]
+pub struct Acknowledge {
+    pub kind: ReceiptKind,
+    pub id: u64,
+}
+
+/// Relayed to the sender of a message once its receiver acknowledges it.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x06, direction = server_to_client)]
+pub struct Receipt {
+    pub kind: ReceiptKind,
+    pub id: u64,
+    /// Who acknowledged it.
+    pub by: String,
+}
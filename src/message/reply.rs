@@ -1,32 +1,45 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use crate::frame::{self, Payload};
+
+use super::ErrorCode;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x02, direction = server_to_client)]
 pub struct MessageReply {
     pub success: bool,
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
     pub message: Option<String>,
     pub extra: Option<Map<String, Value>>,
 }
 
 impl MessageReply {
-    pub fn new(success: bool, message: Option<String>, extra: Option<Map<String, Value>>) -> Self {
+    pub fn new(
+        success: bool,
+        code: Option<ErrorCode>,
+        message: Option<String>,
+        extra: Option<Map<String, Value>>,
+    ) -> Self {
         Self {
             success,
+            code,
             message,
             extra,
         }
     }
 
     pub fn success(message: Option<String>) -> Self {
-        Self::new(true, message, None)
+        Self::new(true, None, message, None)
     }
 
-    pub fn failed(message: Option<String>) -> Self {
-        Self::new(false, message, None)
+    pub fn failed(code: ErrorCode, message: Option<String>) -> Self {
+        Self::new(false, Some(code), message, None)
     }
 
-    pub fn error(err: impl std::error::Error) -> Self {
-        Self::failed(Some(err.to_string()))
+    pub fn error(err: frame::Error) -> Self {
+        Self::failed((&err).into(), Some(err.to_string()))
     }
 
     pub fn put_extra(&mut self, key: impl ToString, value: Value) -> &mut Self {
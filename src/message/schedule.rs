@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Payload;
+
+use super::{ClientMessage, ErrorCode};
+
+/// Sends `message` once `deliver_at` comes, in milliseconds since the Unix epoch.
+///
+/// Answered with a `MessageReply` carrying `extra.schedule_id`; the message itself
+/// goes through the same checks and replies as if it were sent at that time, with
+/// `extra.schedule_id` too. A message to an offline receiver is tried again until
+/// they're online, one failing otherwise stays listed with its error until
+/// cancelled.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x0B, direction = client_to_server)]
+pub struct ScheduleMessage {
+    pub deliver_at: u64,
+    pub message: ClientMessage,
+}
+
+/// Asks for the client's messages still waiting to be sent, or which failed to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Payload)]
+#[payload(code = 0x0C, direction = client_to_server)]
+pub struct ListScheduled;
+
+/// Drops a message the client scheduled before it's sent.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x0D, direction = client_to_server)]
+pub struct CancelScheduled {
+    pub id: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x0C, direction = server_to_client)]
+pub struct ScheduledList {
+    /// Soonest first.
+    pub messages: Vec<ScheduledMessage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ScheduledMessage {
+    pub id: u64,
+    pub deliver_at: u64,
+    pub message: ClientMessage,
+    /// Why the last attempt to send it failed.
+    #[serde(default)]
+    pub error: Option<ErrorCode>,
+}
@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Payload;
+
+use super::ErrorCode;
+
+//
+// Uploading: `UploadRequest` → `UploadReply`, then `UploadChunk` → `UploadReply`
+// until the reply carries a `blob_id`. Sending the request again with the
+// `transfer_id` of an interrupted upload resumes it at the returned offset.
+//
+// Downloading: `DownloadRequest` → `DownloadChunk`, one request per chunk, so
+// chat frames on the same connection never queue up behind a whole file.
+//
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x10, direction = client_to_server)]
+pub struct UploadRequest {
+    /// The transfer to resume, `None` starts a new one.
+    #[serde(default)]
+    pub transfer_id: Option<String>,
+    pub name: String,
+    pub size: u64,
+    pub mime: String,
+    /// SHA-256 of the whole file, hex encoded.
+    pub checksum: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x10, direction = server_to_client)]
+pub struct UploadReply {
+    pub success: bool,
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
+    pub message: Option<String>,
+    pub transfer_id: Option<String>,
+    /// Offset of the next chunk to send.
+    pub offset: u64,
+    /// Max length of chunk data the server accepts.
+    pub chunk_size: usize,
+    /// Set once the upload is complete.
+    pub blob_id: Option<String>,
+    /// Where the blob can be fetched over HTTP, e.g. for `Content::Image`.
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+impl UploadReply {
+    pub fn success(transfer_id: String, offset: u64, chunk_size: usize) -> Self {
+        Self {
+            success: true,
+            code: None,
+            message: None,
+            transfer_id: Some(transfer_id),
+            offset,
+            chunk_size,
+            blob_id: None,
+            url: None,
+        }
+    }
+
+    pub fn failed(transfer_id: Option<String>, code: ErrorCode) -> Self {
+        Self {
+            success: false,
+            code: Some(code),
+            message: None,
+            transfer_id,
+            offset: 0,
+            chunk_size: 0,
+            blob_id: None,
+            url: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x11, direction = client_to_server)]
+pub struct UploadChunk {
+    pub transfer_id: String,
+    pub offset: u64,
+    #[serde(with = "chunk_data")]
+    pub data: Vec<u8>,
+    /// CRC-32 of `data`.
+    pub checksum: u32,
+}
+
+impl UploadChunk {
+    pub fn new(transfer_id: String, offset: u64, data: Vec<u8>) -> Self {
+        let checksum = crc32fast::hash(&data);
+        Self {
+            transfer_id,
+            offset,
+            data,
+            checksum,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x12, direction = client_to_server)]
+pub struct DownloadRequest {
+    pub blob_id: String,
+    pub offset: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x12, direction = server_to_client)]
+pub struct DownloadChunk {
+    pub success: bool,
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
+    pub blob_id: String,
+    pub offset: u64,
+    /// Size of the whole blob.
+    pub size: u64,
+    #[serde(with = "chunk_data")]
+    pub data: Vec<u8>,
+    /// CRC-32 of `data`.
+    pub checksum: u32,
+}
+
+impl DownloadChunk {
+    pub fn new(blob_id: String, offset: u64, size: u64, data: Vec<u8>) -> Self {
+        let checksum = crc32fast::hash(&data);
+        Self {
+            success: true,
+            code: None,
+            blob_id,
+            offset,
+            size,
+            data,
+            checksum,
+        }
+    }
+
+    pub fn failed(blob_id: String, code: ErrorCode) -> Self {
+        Self {
+            success: false,
+            code: Some(code),
+            blob_id,
+            offset: 0,
+            size: 0,
+            data: Vec::new(),
+            checksum: 0,
+        }
+    }
+
+    /// Whether `data` arrived intact.
+    pub fn is_intact(&self) -> bool {
+        crc32fast::hash(&self.data) == self.checksum
+    }
+}
+
+/// Chunk data is raw bytes in binary encodings and base64 in textual ones.
+mod chunk_data {
+    use std::fmt;
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{
+        de::{self, SeqAccess, Visitor},
+        Deserializer, Serializer,
+    };
+
+    pub fn serialize<S>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&STANDARD.encode(data))
+        } else {
+            serializer.serialize_bytes(data)
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(DataVisitor)
+        } else {
+            deserializer.deserialize_bytes(DataVisitor)
+        }
+    }
+
+    struct DataVisitor;
+
+    impl<'de> Visitor<'de> for DataVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("bytes or a base64 string")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            STANDARD.decode(v).map_err(E::custom)
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element()? {
+                data.push(byte);
+            }
+            Ok(data)
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Payload;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TypingState {
+    Typing,
+    Stopped,
+}
+
+/// Tells `receiver` the client is typing, or stopped.
+///
+/// Neither persisted nor answered with a `MessageReply`.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x07, direction = client_to_server)]
+pub struct Typing {
+    pub receiver: String,
+    pub state: TypingState,
+}
+
+/// Forwarded from `sender`, who's typing to the client or stopped.
+///
+/// `Stopped` is also sent by the server once `Typing` times out.
+#[derive(Debug, Serialize, Deserialize, Clone, Payload)]
+#[payload(code = 0x07, direction = server_to_client)]
+pub struct PeerTyping {
+    pub sender: String,
+    pub state: TypingState,
+}
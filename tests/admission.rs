@@ -0,0 +1,93 @@
+mod support;
+
+use std::time::Duration;
+
+use sine_chat::{
+    handler::Config,
+    message::{ErrorCode, HandshakeReply},
+};
+use tokio::time;
+
+use support::TestServer;
+
+#[tokio::test]
+async fn caps_connections() {
+    let server = TestServer::start_with_config(Config {
+        max_connections: 2,
+        ..Default::default()
+    })
+    .await;
+    let alice = server.login("alice").await;
+    // Connections count before their handshake too.
+    let _pending = server.connect().await;
+
+    let mut rejected = server.connect().await;
+    rejected.expect_closed(Duration::from_secs(1)).await;
+
+    // The slot is freed once the server notices the disconnect.
+    drop(alice);
+    time::sleep(Duration::from_millis(100)).await;
+    server.login("bob").await;
+}
+
+#[tokio::test]
+async fn caps_connections_per_ip() {
+    let server = TestServer::start_with_config(Config {
+        max_connections_per_ip: 1,
+        ..Default::default()
+    })
+    .await;
+    let alice = server.login("alice").await;
+
+    let mut rejected = server.connect().await;
+    rejected.expect_closed(Duration::from_secs(1)).await;
+
+    drop(alice);
+    time::sleep(Duration::from_millis(100)).await;
+    server.login("bob").await;
+}
+
+#[tokio::test]
+async fn bans_after_failed_handshakes() {
+    let server = TestServer::start_with_config(Config {
+        max_failed_handshakes: 2,
+        handshake_ban: Duration::from_millis(300),
+        ..Default::default()
+    })
+    .await;
+    for _ in 0..2 {
+        let mut client = server.connect().await;
+        client.send_raw(0x00, b"[]").await;
+        let reply = client.expect::<HandshakeReply>().await;
+        assert_eq!(reply.code, Some(ErrorCode::MalformedPayload));
+    }
+
+    let mut banned = server.connect().await;
+    banned.expect_closed(Duration::from_secs(1)).await;
+
+    time::sleep(Duration::from_millis(300)).await;
+    server.login("alice").await;
+}
+
+#[tokio::test]
+async fn bans_only_misbehaving_handshakes() {
+    let server = TestServer::start_with_config(Config {
+        max_failed_handshakes: 2,
+        handshake_timeout: Duration::from_millis(100),
+        handshake_ban: Duration::from_secs(10),
+        ..Default::default()
+    })
+    .await;
+    let _alice = server.login("alice").await;
+    for _ in 0..2 {
+        let mut client = server.connect().await;
+        let reply = client.handshake("alice").await;
+        assert_eq!(reply.code, Some(ErrorCode::UserExists));
+        // Neither do connections that never get to a handshake count.
+        drop(server.connect().await);
+        let mut idle = server.connect().await;
+        idle.expect_closed(Duration::from_secs(1)).await;
+    }
+
+    server.login("bob").await;
+}
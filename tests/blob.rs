@@ -0,0 +1,441 @@
+mod support;
+
+use std::{
+    io::Cursor,
+    net::{SocketAddr, TcpListener as StdListener},
+    time::Duration,
+};
+
+use image::{ImageFormat, Rgb, RgbImage};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sine_chat::{
+    handler::Config,
+    message::{
+        ClientMessage, Content, ErrorCode, MessageReply, ServerMessage, UploadChunk, UploadReply,
+        UploadRequest,
+    },
+};
+use tempfile::TempDir;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time,
+};
+
+use support::{TestClient, TestServer};
+
+struct Setup {
+    server: TestServer,
+    http: SocketAddr,
+    dir: TempDir,
+}
+
+async fn start() -> Setup {
+    start_with_config(Config::default()).await
+}
+
+async fn start_with_config(config: Config) -> Setup {
+    let dir = tempfile::tempdir().unwrap();
+    let http = StdListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let config = Config {
+        blob_dir: dir.path().into(),
+        http_addr: Some(http),
+        ..config
+    };
+    let server = TestServer::start_with_config(config).await;
+    // The HTTP endpoint is bound in the background.
+    while TcpStream::connect(http).await.is_err() {
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    Setup { server, http, dir }
+}
+
+/// Sends a raw HTTP request, returns the status along with the body.
+async fn http(addr: SocketAddr, head: &str, body: &[u8]) -> (u16, Vec<u8>) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(head.as_bytes()).await.unwrap();
+    stream.write_all(body).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let status = String::from_utf8_lossy(&response[9..12]).parse().unwrap();
+    (status, response[split + 4..].to_vec())
+}
+
+async fn get(addr: SocketAddr, url: &str, token: Option<&str>) -> (u16, Vec<u8>) {
+    let path = &url[url.find("/blobs/").unwrap()..];
+    let auth = token
+        .map(|token| format!("Authorization: Bearer {}\r\n", token))
+        .unwrap_or_default();
+    let head = format!("GET {} HTTP/1.1\r\nHost: {}\r\n{}\r\n", path, addr, auth);
+    http(addr, &head, b"").await
+}
+
+/// Uploads `data` over the frame protocol, returns the final reply.
+async fn upload(client: &mut TestClient, data: &[u8]) -> UploadReply {
+    client
+        .send(UploadRequest {
+            transfer_id: None,
+            name: "cat.png".into(),
+            size: data.len() as u64,
+            mime: "image/png".into(),
+            checksum: format!("{:x}", Sha256::digest(data)),
+        })
+        .await;
+    let mut reply = client.expect::<UploadReply>().await;
+    while reply.blob_id.is_none() {
+        let transfer_id = reply.transfer_id.clone().unwrap();
+        let start = reply.offset as usize;
+        let end = (start + reply.chunk_size).min(data.len());
+        let chunk = UploadChunk::new(transfer_id, reply.offset, data[start..end].to_vec());
+        client.send(chunk).await;
+        reply = client.expect::<UploadReply>().await;
+        assert!(reply.success, "Chunk rejected: {:?}", reply);
+    }
+    reply
+}
+
+fn stored_blobs(dir: &TempDir) -> usize {
+    std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file() && path.extension().is_none())
+        .count()
+}
+
+/// Number of stored blobs `uid` may read.
+fn readable_by(dir: &TempDir, uid: &str) -> usize {
+    std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter(|path| {
+            let meta: Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+            meta["readers"].as_array().unwrap().iter().any(|r| r == uid)
+        })
+        .count()
+}
+
+#[tokio::test]
+async fn stores_blobs_by_content() {
+    let setup = start().await;
+    let mut alice = setup.server.login("alice").await;
+    let mut bob = setup.server.login("bob").await;
+    let data = b"the same picture".repeat(10);
+
+    let reply = upload(&mut alice, &data).await;
+    let blob_id = reply.blob_id.unwrap();
+    assert_eq!(blob_id, format!("{:x}", Sha256::digest(&data)));
+    let url = reply.url.unwrap();
+    assert_eq!(url, format!("http://{}/blobs/{}", setup.http, blob_id));
+
+    // Readers skip re-uploading.
+    alice
+        .send(UploadRequest {
+            transfer_id: None,
+            name: "again.png".into(),
+            size: data.len() as u64,
+            mime: "image/png".into(),
+            checksum: blob_id.clone(),
+        })
+        .await;
+    let reply = alice.expect::<UploadReply>().await;
+    assert_eq!(reply.blob_id.as_ref(), Some(&blob_id));
+    assert_eq!(reply.offset, data.len() as u64);
+
+    // Others have to prove they have the content, which is then stored once.
+    let reply = upload(&mut bob, &data).await;
+    assert_eq!(reply.blob_id, Some(blob_id));
+    assert_eq!(stored_blobs(&setup.dir), 1);
+    assert_eq!(
+        get(setup.http, &url, bob.http_token.as_deref()).await.0,
+        200
+    );
+}
+
+#[tokio::test]
+async fn serves_blobs_to_conversation_members() {
+    let setup = start().await;
+    let mut alice = setup.server.login("alice").await;
+    let mut bob = setup.server.login("bob").await;
+    let data = png(10, 10);
+    let url = upload(&mut alice, &data).await.url.unwrap();
+
+    let carol = setup.server.login("carol").await;
+    let (alice_token, bob_token) = (alice.http_token.as_deref(), bob.http_token.as_deref());
+    assert_eq!(
+        get(setup.http, &url, alice_token).await,
+        (200, data.clone())
+    );
+    assert_eq!(get(setup.http, &url, bob_token).await.0, 404);
+    assert_eq!(get(setup.http, &url, None).await.0, 401);
+    // Knowing the user name isn't enough.
+    assert_eq!(get(setup.http, &url, Some("alice")).await.0, 401);
+
+    let content = Content::Image {
+        url: url.clone(),
+        width: 10.0,
+        height: 10.0,
+        thumbnails: Vec::new(),
+    };
+    alice.send(ClientMessage::new(content, "bob".into())).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    bob.expect::<ServerMessage>().await;
+
+    let bob_token = bob.http_token.as_deref();
+    assert_eq!(get(setup.http, &url, bob_token).await, (200, data));
+    assert_eq!(
+        get(setup.http, &url, carol.http_token.as_deref()).await.0,
+        404
+    );
+
+    // Tokens end with their session.
+    let bob_token = bob.http_token.clone();
+    drop(bob);
+    time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(get(setup.http, &url, bob_token.as_deref()).await.0, 401);
+}
+
+#[tokio::test]
+async fn shares_blobs_only_with_accepted_messages() {
+    let setup = start().await;
+    let mut alice = setup.server.login("alice").await;
+    let data = b"a private file".to_vec();
+    let reply = upload(&mut alice, &data).await;
+    let url = reply.url.unwrap();
+    let content = Content::File {
+        blob_id: reply.blob_id.unwrap(),
+        name: "private.png".into(),
+        size: data.len() as u64,
+        mime: "image/png".into(),
+    };
+
+    alice
+        .send(ClientMessage::new(content.clone(), "bob".into()))
+        .await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::ReceiverNotFound));
+    assert_eq!(readable_by(&setup.dir, "bob"), 0);
+
+    let bob = setup.server.login("bob").await;
+    let mut message = ClientMessage::new(content, "bob".into());
+    message.reply_to = Some(12345);
+    alice.send(message).await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::MessageNotFound));
+    assert_eq!(
+        get(setup.http, &url, bob.http_token.as_deref()).await.0,
+        404
+    );
+}
+
+#[tokio::test]
+async fn rejects_misdeclared_files() {
+    let setup = start().await;
+    let mut alice = setup.server.login("alice").await;
+    let mut bob = setup.server.login("bob").await;
+    let data = png(10, 10);
+    let blob_id = upload(&mut alice, &data).await.blob_id.unwrap();
+    let file = |size: usize, mime: &str| Content::File {
+        blob_id: blob_id.clone(),
+        name: "cat.png".into(),
+        size: size as u64,
+        mime: mime.into(),
+    };
+
+    for content in [file(1, "image/png"), file(data.len(), "text/plain")] {
+        alice.send(ClientMessage::new(content, "bob".into())).await;
+        let reply = alice.expect::<MessageReply>().await;
+        assert_eq!(reply.code, Some(ErrorCode::MalformedPayload));
+    }
+    assert_eq!(readable_by(&setup.dir, "bob"), 0);
+
+    let content = file(data.len(), "image/png");
+    alice.send(ClientMessage::new(content, "bob".into())).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    bob.expect::<ServerMessage>().await;
+}
+
+#[tokio::test]
+async fn uploads_over_http() {
+    let setup = start().await;
+    let alice = setup.server.login("alice").await;
+    let mut bob = setup.server.login("bob").await;
+    let data = b"posted picture";
+    let alice_token = alice.http_token.as_deref();
+
+    let head = format!(
+        "POST /blobs HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n",
+        alice_token.unwrap(),
+        data.len()
+    );
+    let (status, body) = http(setup.http, &head, data).await;
+    assert_eq!(status, 201);
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    let blob_id = body["blob_id"].as_str().unwrap();
+    assert_eq!(blob_id, format!("{:x}", Sha256::digest(data)));
+    let url = body["url"].as_str().unwrap();
+    assert_eq!(
+        get(setup.http, url, alice_token).await,
+        (200, data.to_vec())
+    );
+
+    // Only readers may hand out a blob.
+    let content = Content::File {
+        blob_id: blob_id.into(),
+        name: "picture.png".into(),
+        size: data.len() as u64,
+        mime: "image/png".into(),
+    };
+    bob.send(ClientMessage::new(content, "carol".into())).await;
+    let reply = bob.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::BlobNotFound));
+}
+
+#[tokio::test]
+async fn rejects_oversized_http_upload() {
+    let setup = start().await;
+    let alice = setup.server.login("alice").await;
+    let head = format!(
+        "POST /blobs HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Length: 999999999999\r\n\r\n",
+        alice.http_token.unwrap()
+    );
+    assert_eq!(http(setup.http, &head, b"").await.0, 413);
+    assert_eq!(stored_blobs(&setup.dir), 0);
+}
+
+#[tokio::test]
+async fn times_out_slow_http_upload() {
+    let setup = start_with_config(Config {
+        frame_timeout: Duration::from_millis(200),
+        min_frame_throughput: 1024 * 1024,
+        ..Default::default()
+    })
+    .await;
+    let alice = setup.server.login("alice").await;
+    let head = format!(
+        "POST /blobs HTTP/1.1\r\nAuthorization: Bearer {}\r\nContent-Length: 100000\r\n\r\n",
+        alice.http_token.unwrap()
+    );
+    let response = time::timeout(
+        Duration::from_secs(2),
+        http(setup.http, &head, b"only the beginning"),
+    )
+    .await;
+    assert_eq!(response.expect("Upload never timed out").0, 408);
+    assert_eq!(stored_blobs(&setup.dir), 0);
+}
+
+// Images
+
+fn png(width: u32, height: u32) -> Vec<u8> {
+    let image = RgbImage::from_fn(width, height, |x, y| Rgb([x as u8, y as u8, 128]));
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    png
+}
+
+/// Sends an image message to bob, returns the reply to alice.
+async fn send_image(alice: &mut TestClient, url: &str, width: f64, height: f64) -> MessageReply {
+    let content = Content::Image {
+        url: url.into(),
+        width,
+        height,
+        thumbnails: Vec::new(),
+    };
+    alice.send(ClientMessage::new(content, "bob".into())).await;
+    alice.expect::<MessageReply>().await
+}
+
+#[tokio::test]
+async fn attaches_thumbnails() {
+    let setup = start().await;
+    let mut alice = setup.server.login("alice").await;
+    let mut bob = setup.server.login("bob").await;
+    let carol = setup.server.login("carol").await;
+    let url = upload(&mut alice, &png(600, 400)).await.url.unwrap();
+
+    assert!(send_image(&mut alice, &url, 600.0, 400.0).await.success);
+    alice.expect::<ServerMessage>().await;
+    let thumbnails = match bob.expect::<ServerMessage>().await.content {
+        Content::Image { thumbnails, .. } => thumbnails,
+        content => panic!("Unexpected content: {:?}", content),
+    };
+    let sizes: Vec<_> = thumbnails.iter().map(|t| (t.width, t.height)).collect();
+    assert_eq!(sizes, [(128, 85), (512, 341)]);
+
+    let (status, body) = get(setup.http, &thumbnails[0].url, bob.http_token.as_deref()).await;
+    assert_eq!(status, 200);
+    let thumbnail = image::load_from_memory(&body).unwrap();
+    assert_eq!((thumbnail.width(), thumbnail.height()), (128, 85));
+    assert_eq!(
+        get(setup.http, &thumbnails[0].url, carol.http_token.as_deref())
+            .await
+            .0,
+        404
+    );
+
+    // Small images need no previews.
+    let url = upload(&mut alice, &png(64, 64)).await.url.unwrap();
+    assert!(send_image(&mut alice, &url, 64.0, 64.0).await.success);
+    alice.expect::<ServerMessage>().await;
+    match bob.expect::<ServerMessage>().await.content {
+        Content::Image { thumbnails, .. } => assert!(thumbnails.is_empty()),
+        content => panic!("Unexpected content: {:?}", content),
+    }
+}
+
+#[tokio::test]
+async fn rejects_invalid_images() {
+    let setup = start_with_config(Config {
+        max_image_dimension: 1000,
+        ..Default::default()
+    })
+    .await;
+    let mut alice = setup.server.login("alice").await;
+    let bob = setup.server.login("bob").await;
+
+    let url = upload(&mut alice, &png(300, 200)).await.url.unwrap();
+    let reply = send_image(&mut alice, &url, 200.0, 300.0).await;
+    assert_eq!(reply.code, Some(ErrorCode::InvalidImage));
+    // Neither the image nor its thumbnails were shared.
+    assert_eq!(
+        get(setup.http, &url, bob.http_token.as_deref()).await.0,
+        404
+    );
+    assert_eq!(readable_by(&setup.dir, "bob"), 0);
+    let content = Content::Image {
+        url: url.clone(),
+        width: 300.0,
+        height: 200.0,
+        thumbnails: Vec::new(),
+    };
+    alice
+        .send(ClientMessage::new(content, "carol".into()))
+        .await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::ReceiverNotFound));
+    assert_eq!(readable_by(&setup.dir, "carol"), 0);
+
+    let url = upload(&mut alice, b"definitely not an image")
+        .await
+        .url
+        .unwrap();
+    let reply = send_image(&mut alice, &url, 1.0, 1.0).await;
+    assert_eq!(reply.code, Some(ErrorCode::InvalidImage));
+
+    let url = upload(&mut alice, &png(1200, 10)).await.url.unwrap();
+    let reply = send_image(&mut alice, &url, 1200.0, 10.0).await;
+    assert_eq!(reply.code, Some(ErrorCode::InvalidImage));
+
+    // Images hosted elsewhere aren't checked.
+    let reply = send_image(&mut alice, "https://example.com/cat.png", 1.0, 1.0).await;
+    assert!(reply.success);
+}
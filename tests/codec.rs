@@ -0,0 +1,115 @@
+mod support;
+
+use bytes::{BufMut, BytesMut};
+use sine_chat::{
+    frame::{Codec, Compression, Error, RawPayload},
+    message::{ClientMessage, Content, Handshake, MessageReply},
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+use support::{TestServer, TIMEOUT};
+
+fn deflate_codec() -> Codec {
+    let mut codec = Codec::new();
+    codec.set_compression(Some(Compression::Deflate));
+    codec
+}
+
+fn encode(codec: &mut Codec, type_code: u8, content: Vec<u8>) -> BytesMut {
+    let mut buf = BytesMut::new();
+    codec
+        .encode(RawPayload::new(type_code, content.into()), &mut buf)
+        .unwrap();
+    buf
+}
+
+fn compressed_flag(frame: &[u8]) -> bool {
+    frame[1] & 0x80 != 0
+}
+
+#[test]
+fn compresses_large_frames() {
+    let mut codec = deflate_codec();
+    let content = "hello ".repeat(1000).into_bytes();
+    let mut frame = encode(&mut codec, 0x01, content.clone());
+    assert!(compressed_flag(&frame));
+    assert!(frame.len() < content.len());
+
+    let payload = codec.decode(&mut frame).unwrap().unwrap();
+    assert_eq!(payload.type_code, 0x01);
+    assert_eq!(payload.content, content);
+}
+
+#[test]
+fn skips_compression_of_small_frames() {
+    let mut codec = deflate_codec();
+    let mut frame = encode(&mut codec, 0xFF, b"null".to_vec());
+    assert!(!compressed_flag(&frame));
+    assert_eq!(codec.decode(&mut frame).unwrap().unwrap().content, "null");
+}
+
+#[test]
+fn rejects_compressed_frame_when_not_negotiated() {
+    let mut frame = encode(&mut deflate_codec(), 0x01, vec![b'a'; 4096]);
+    let result = Codec::new().decode(&mut frame);
+    assert!(matches!(result, Err(Error::Compression(_))));
+}
+
+#[test]
+fn rejects_compression_bomb() {
+    let mut sender = deflate_codec();
+    sender.set_max_payload_length(64 * 1024 * 1024);
+    let mut frame = encode(&mut sender, 0x01, vec![0; 16 * 1024 * 1024]);
+    assert!(frame.len() < 1024 * 1024);
+
+    let mut receiver = deflate_codec();
+    let result = receiver.decode(&mut frame);
+    assert!(matches!(result, Err(Error::TooLarge(_))));
+}
+
+#[test]
+fn skips_oversized_frame_and_keeps_decoding() {
+    let mut codec = Codec::new();
+    codec.set_max_payload_length(8);
+
+    let mut buf = BytesMut::new();
+    buf.put_u8(0x01);
+    buf.put_u32(16);
+    buf.put_slice(&[0; 10]);
+    assert!(matches!(codec.decode(&mut buf), Err(Error::TooLarge(16))));
+    assert!(codec.decode(&mut buf).unwrap().is_none());
+
+    // The rest of the oversized payload arrives together with the next frame.
+    buf.put_slice(&[0; 6]);
+    buf.put_u8(0xFF);
+    buf.put_u32(4);
+    buf.put_slice(b"null");
+    let payload = codec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(payload.type_code, 0xFF);
+    assert_eq!(payload.content, "null");
+}
+
+#[tokio::test]
+async fn compressed_and_plain_clients_interoperate() {
+    let server = TestServer::start().await;
+
+    let mut alice = server.connect().await;
+    let mut handshake = Handshake::new("alice".into());
+    handshake.compressions = vec![Compression::Unknown, Compression::Deflate];
+    let reply = alice.handshake_with(handshake).await;
+    assert_eq!(reply.compression, Some(Compression::Deflate));
+
+    let mut bob = server.login("bob").await;
+
+    let text = "lorem ipsum ".repeat(500);
+    alice
+        .send(ClientMessage::new(
+            Content::Text(text.clone()),
+            "bob".into(),
+        ))
+        .await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect_message_from("alice", TIMEOUT).await;
+    let message = bob.expect_message_from("alice", TIMEOUT).await;
+    assert!(matches!(message.content, Content::Text(ref t) if *t == text));
+}
@@ -0,0 +1,229 @@
+mod support;
+
+use serde_json::json;
+use sine_chat::{
+    frame::{Encoding, RawPayload},
+    message::{ClientMessage, Content, ErrorCode, MentionSpan, MessageReply, ServerMessage},
+};
+
+use support::{TestServer, TIMEOUT};
+
+#[test]
+fn decodes_old_message_shape() {
+    let message: ServerMessage =
+        serde_json::from_str(r#"{"type":"text","content":"hi","sender":"alice","receiver":"bob"}"#)
+            .unwrap();
+    assert!(matches!(message.content, Content::Text(ref text) if text == "hi"));
+    assert!(message.fallback.is_none());
+    assert_eq!(message.display_content(), "hi");
+}
+
+#[test]
+fn keeps_unknown_content_as_is() {
+    let json = json!({
+        "id": 7,
+        "timestamp": 1700000000000u64,
+        "type": "poll",
+        "content": { "question": "Lunch?", "options": ["Noodles", "Rice"] },
+        "sender": "alice",
+        "receiver": "bob",
+        "fallback": "Poll: Lunch?",
+    });
+    let message: ServerMessage = serde_json::from_value(json.clone()).unwrap();
+    match &message.content {
+        Content::Unknown { r#type, raw } => {
+            assert_eq!(r#type, "poll");
+            assert_eq!(raw["options"][1], "Rice");
+        }
+        content => panic!("Unexpected content: {:?}", content),
+    }
+    assert_eq!(message.display_content(), "Poll: Lunch?");
+
+    // Relaying re-encodes the same shape.
+    assert_eq!(serde_json::to_value(&message).unwrap(), json);
+}
+
+#[test]
+fn keeps_unknown_content_in_message_pack() {
+    let content = Content::Unknown {
+        r#type: "poll".into(),
+        raw: json!({ "options": ["Noodles", "Rice"] }),
+    };
+    let message = ServerMessage::new(content, "alice".into(), "bob".into());
+    let raw = RawPayload::from_payload(message, Encoding::MessagePack).unwrap();
+    let message: ServerMessage = raw.into_payload(Encoding::MessagePack).unwrap();
+    assert!(
+        matches!(message.content, Content::Unknown { ref raw, .. } if raw["options"][0] == "Noodles")
+    );
+}
+
+#[test]
+fn rejects_malformed_known_content() {
+    let result = serde_json::from_str::<Content>(r#"{"type":"image","content":"not an image"}"#);
+    assert!(result.is_err());
+}
+
+#[test]
+fn shows_placeholder_without_fallback() {
+    let content: Content = serde_json::from_str(r#"{"type":"poll"}"#).unwrap();
+    assert_eq!(content.to_string(), "[Unsupported content: poll]");
+}
+
+#[tokio::test]
+async fn relays_unknown_content_with_fallback() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    // A newer client sending a content type this version doesn't know.
+    alice
+        .send_raw(
+            0x01,
+            br#"{"content":{"type":"poll","content":{"question":"Lunch?"}},"receiver":"bob","fallback":"Poll: Lunch?"}"#,
+        )
+        .await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect_message_from("alice", TIMEOUT).await;
+
+    let message = bob.expect_message_from("alice", TIMEOUT).await;
+    assert!(message.content.is_unknown());
+    assert_eq!(message.fallback.as_deref(), Some("Poll: Lunch?"));
+    assert_eq!(message.display_content(), "Poll: Lunch?");
+}
+
+#[tokio::test]
+async fn old_message_shape_still_accepted() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    alice
+        .send_raw(
+            0x01,
+            br#"{"content":{"type":"text","content":"hi"},"receiver":"bob"}"#,
+        )
+        .await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect_message_from("alice", TIMEOUT).await;
+    let message = bob.expect_message_from("alice", TIMEOUT).await;
+    assert_eq!(message.display_content(), "hi");
+
+    // New shapes are accepted by the same path.
+    let mut message = ClientMessage::new(Content::Text("hey".into()), "alice".into());
+    message.fallback = Some("hey".into());
+    bob.send(message).await;
+    let message = alice.expect_message_from("bob", TIMEOUT).await;
+    assert_eq!(message.fallback.as_deref(), Some("hey"));
+}
+
+fn markdown(text: &str) -> Content {
+    Content::Markdown(text.into())
+}
+
+#[test]
+fn validates_markdown() {
+    for valid in [
+        "**Lunch** at *noon*, `12:00` [here](https://example.com/map)",
+        "2 * 3 = 6\n* a bullet\n# Heading",
+        "Escaped \\*star",
+        "```\nlet x = *y;\n```",
+    ] {
+        assert!(markdown(valid).validate().is_ok(), "{:?}", valid);
+    }
+    for invalid in [
+        "**bold",
+        "*italic",
+        "`code",
+        "```\nunclosed",
+        "[click](javascript:alert(1))",
+        "[broken](https://example.com",
+    ] {
+        assert!(markdown(invalid).validate().is_err(), "{:?}", invalid);
+    }
+}
+
+#[test]
+fn shows_rich_content_as_text() {
+    let content = markdown("# Menu\n**Lunch** at *noon* [map](https://example.com)");
+    assert_eq!(
+        content.to_string(),
+        "Menu\nLunch at noon map (https://example.com)"
+    );
+
+    let content = Content::Location {
+        latitude: 31.2,
+        longitude: 121.5,
+        name: None,
+    };
+    assert_eq!(content.to_string(), "[Location: 31.2, 121.5]");
+    let content = Content::Location {
+        latitude: 31.2,
+        longitude: 121.5,
+        name: Some("The Bund".into()),
+    };
+    assert_eq!(content.to_string(), "[Location: The Bund]");
+
+    let content = mentions("hi @bob", &[("bob", 3, 7)]);
+    assert_eq!(content.to_string(), "hi @bob");
+}
+
+fn mentions(text: &str, spans: &[(&str, usize, usize)]) -> Content {
+    let mentions = spans
+        .iter()
+        .map(|&(user, start, end)| MentionSpan {
+            user: user.into(),
+            start,
+            end,
+        })
+        .collect();
+    Content::Mentions {
+        text: text.into(),
+        mentions,
+    }
+}
+
+#[test]
+fn validates_mentions_and_locations() {
+    let text = "@alice and @bob";
+    assert!(mentions(text, &[("alice", 0, 6), ("bob", 11, 15)])
+        .validate()
+        .is_ok());
+    for spans in [
+        [("alice", 0, 6), ("bob", 11, 16)],
+        [("alice", 1, 6), ("bob", 11, 15)],
+        [("alice", 0, 6), ("", 11, 15)],
+        [("bob", 11, 15), ("alice", 0, 6)],
+    ] {
+        assert!(mentions(text, &spans).validate().is_err(), "{:?}", spans);
+    }
+
+    let location = Content::Location {
+        latitude: 91.0,
+        longitude: 0.0,
+        name: None,
+    };
+    assert!(location.validate().is_err());
+}
+
+#[tokio::test]
+async fn validates_content_before_routing() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    let message = ClientMessage::new(markdown("**unclosed"), "bob".into());
+    alice.send(message).await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::MalformedPayload));
+    assert_eq!(reply.message.as_deref(), Some("Unclosed **"));
+
+    let location = Content::Location {
+        latitude: 31.2,
+        longitude: 121.5,
+        name: Some("The Bund".into()),
+    };
+    alice.send(ClientMessage::new(location, "bob".into())).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    let message = bob.expect_message_from("alice", TIMEOUT).await;
+    assert!(matches!(message.content, Content::Location { latitude, .. } if latitude == 31.2));
+}
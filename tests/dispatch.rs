@@ -0,0 +1,58 @@
+use sine_chat::{
+    frame::{
+        messages::{client, Inbound},
+        Encoding, Error, RawPayload, Reader, ReceivablePayload, ServerSide, Writer,
+    },
+    message::{MessageReply, Pong},
+};
+
+fn raw(type_code: u8, content: &'static str) -> RawPayload {
+    RawPayload::new(type_code, content.into())
+}
+
+#[test]
+fn dispatches_by_type_code() {
+    let ping = raw(0xFF, "null")
+        .into_payload::<Inbound>(Encoding::Json)
+        .unwrap();
+    assert!(matches!(ping, Inbound::Ping(_)));
+
+    let message = raw(
+        0x01,
+        r#"{"content":{"type":"text","content":"hi"},"receiver":"bob"}"#,
+    )
+    .into_payload::<Inbound>(Encoding::Json)
+    .unwrap();
+    assert!(matches!(message, Inbound::ClientMessage(msg) if msg.receiver == "bob"));
+}
+
+#[test]
+fn rejects_unknown_type_code() {
+    let result = raw(0x02, "{}").into_payload::<Inbound>(Encoding::Json);
+    assert!(matches!(result, Err(Error::TypeMismatch(0x02))));
+    assert!(!Inbound::accepts(0x02));
+    assert!(client::Inbound::accepts(0x02));
+}
+
+#[test]
+fn reports_coding_error_of_selected_variant() {
+    let result = raw(0x01, "{").into_payload::<Inbound>(Encoding::Json);
+    assert!(matches!(result, Err(Error::Coding(_))));
+}
+
+#[tokio::test]
+async fn reader_rejects_frames_of_the_other_direction() {
+    let (client, server) = tokio::io::duplex(1024);
+    // A misbehaving peer writing server-only frames towards the server.
+    let mut writer = Writer::<_, ServerSide>::new(client);
+    let mut reader = Reader::<_, ServerSide>::new(server);
+
+    writer.write(MessageReply::success(None)).await.unwrap();
+    let result = reader.read_any().await.unwrap();
+    assert!(matches!(result, Err(Error::WrongDirection(0x02))));
+
+    // Codes used by both directions are decoded as the inbound payload.
+    writer.write(Pong).await.unwrap();
+    let result = reader.read_any().await.unwrap();
+    assert!(matches!(result, Ok(Inbound::Ping(_))));
+}
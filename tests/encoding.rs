@@ -0,0 +1,92 @@
+mod support;
+
+use serde_json::json;
+use sine_chat::{
+    frame::{Encoding, RawPayload},
+    message::{
+        ClientMessage, Content, ErrorCode, Handshake, MessageReply, Ping, Pong, ServerMessage,
+    },
+};
+
+use support::{TestServer, TIMEOUT};
+
+#[test]
+fn message_pack_round_trips_payloads() {
+    let content = Content::Image {
+        url: "https://example.com/a.png".into(),
+        width: 640.0,
+        height: 480.0,
+        thumbnails: Vec::new(),
+    };
+    let message = ServerMessage::new(content, "alice".into(), "bob".into());
+    let raw = RawPayload::from_payload(message, Encoding::MessagePack).unwrap();
+    let decoded: ServerMessage = raw.into_payload(Encoding::MessagePack).unwrap();
+    assert_eq!(decoded.sender, "alice");
+    assert!(matches!(decoded.content, Content::Image { width, .. } if width == 640.0));
+
+    let mut reply = MessageReply::failed(ErrorCode::TooLarge, Some("detail".into()));
+    reply.put_extra("limit", json!(1024));
+    let raw = RawPayload::from_payload(reply, Encoding::MessagePack).unwrap();
+    let decoded: MessageReply = raw.into_payload(Encoding::MessagePack).unwrap();
+    assert_eq!(decoded.code, Some(ErrorCode::TooLarge));
+    assert_eq!(decoded.extra.unwrap()["limit"], json!(1024));
+
+    let raw = RawPayload::from_payload(Ping, Encoding::MessagePack).unwrap();
+    raw.into_payload::<Ping>(Encoding::MessagePack).unwrap();
+}
+
+#[test]
+fn json_payload_is_not_message_pack() {
+    let raw = RawPayload::from_payload(Pong, Encoding::Json).unwrap();
+    let raw = RawPayload::new(0x01, raw.content);
+    assert!(raw
+        .into_payload::<ServerMessage>(Encoding::MessagePack)
+        .is_err());
+}
+
+#[test]
+fn negotiates_first_supported_encoding() {
+    let handshake: Handshake =
+        serde_json::from_str(r#"{"token":"alice","encodings":["zstd_msgpack","message_pack"]}"#)
+            .unwrap();
+    assert_eq!(handshake.encodings[0], Encoding::Unknown);
+    assert_eq!(
+        Encoding::negotiate(&handshake.encodings),
+        Encoding::MessagePack
+    );
+    assert_eq!(Encoding::negotiate(&[]), Encoding::Json);
+
+    // Older clients don't send the field at all.
+    let handshake: Handshake = serde_json::from_str(r#"{"token":"alice"}"#).unwrap();
+    assert!(handshake.encodings.is_empty());
+}
+
+#[tokio::test]
+async fn message_pack_and_json_clients_interoperate() {
+    let server = TestServer::start().await;
+
+    let mut alice = server.connect().await;
+    let mut handshake = Handshake::new("alice".into());
+    handshake.encodings = vec![Encoding::MessagePack];
+    let reply = alice.handshake_with(handshake).await;
+    assert!(reply.success);
+    assert_eq!(reply.encoding, Encoding::MessagePack);
+
+    let mut bob = server.login("bob").await;
+
+    alice
+        .send(ClientMessage::new(Content::Text("hi".into()), "bob".into()))
+        .await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect_message_from("alice", TIMEOUT).await;
+    let message = bob.expect_message_from("alice", TIMEOUT).await;
+    assert!(matches!(message.content, Content::Text(ref text) if text == "hi"));
+
+    bob.send(ClientMessage::new(
+        Content::Text("yo".into()),
+        "alice".into(),
+    ))
+    .await;
+    let message = alice.expect_message_from("bob", TIMEOUT).await;
+    assert!(matches!(message.content, Content::Text(ref text) if text == "yo"));
+}
@@ -0,0 +1,85 @@
+mod support;
+
+use std::time::Duration;
+
+use sine_chat::{
+    handler::Config,
+    message::{ClientMessage, ErrorCode, MessageExpired, MessageReply},
+};
+
+use tokio::time;
+
+use support::{chat, deliver, history, text, TestClient, TestServer, TIMEOUT};
+
+/// `content` to bob, expiring `ttl` after it's sent.
+fn with_ttl(content: &str, ttl: u64) -> ClientMessage {
+    let mut message = ClientMessage::new(text(content), "bob".into());
+    message.ttl = Some(ttl);
+    message
+}
+
+async fn expect_expired(client: &mut TestClient, id: u64) {
+    let expired = client
+        .expect_within::<MessageExpired>(Duration::from_secs(2))
+        .await;
+    assert_eq!(expired.id, id);
+}
+
+#[tokio::test]
+async fn expires_messages() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    let message = deliver(&mut alice, &mut bob, with_ttl("self-destructing", 200)).await;
+    assert_eq!(message.expires_at, Some(message.timestamp + 200));
+    let kept = chat(&mut alice, &mut bob, text("kept")).await;
+    assert!(kept.expires_at.is_none());
+
+    expect_expired(&mut alice, message.id).await;
+    expect_expired(&mut bob, message.id).await;
+    let messages = history(&mut bob, "alice").await.messages;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].id, kept.id);
+
+    let mut message = ClientMessage::new(text("gone"), "bob".into());
+    message.ttl = Some(0);
+    alice.send(message).await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::MalformedPayload));
+}
+
+#[tokio::test]
+async fn expires_after_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("history.jsonl");
+    let config = Config {
+        history_path: Some(path.clone()),
+        history_scrub_delay: Duration::from_millis(200),
+        ..Default::default()
+    };
+
+    let server = TestServer::start_with_config(config.clone()).await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let message = deliver(&mut alice, &mut bob, with_ttl("top secret", 500)).await;
+
+    // A fresh server on the same log stands in for a restart.
+    server.stop().await;
+    alice.expect_closed(TIMEOUT).await;
+    let server = TestServer::start_with_config(config).await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    assert_eq!(history(&mut bob, "alice").await.messages.len(), 1);
+    expect_expired(&mut alice, message.id).await;
+    expect_expired(&mut bob, message.id).await;
+
+    assert!(history(&mut bob, "alice").await.messages.is_empty());
+    time::sleep(Duration::from_millis(400)).await;
+    let log = std::fs::read_to_string(&path).unwrap();
+    assert!(!log.contains("top secret"), "Content left in log: {}", log);
+
+    // Ids of expired messages aren't handed out again.
+    let next = chat(&mut alice, &mut bob, text("hi")).await;
+    assert!(next.id > message.id);
+}
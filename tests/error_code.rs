@@ -0,0 +1,22 @@
+use sine_chat::message::{ErrorCode, MessageReply};
+
+#[test]
+fn error_codes_are_snake_case_strings() {
+    let json = serde_json::to_string(&ErrorCode::ReceiverNotFound).unwrap();
+    assert_eq!(json, r#""receiver_not_found""#);
+}
+
+#[test]
+fn unknown_error_code_decodes_as_unknown() {
+    let reply: MessageReply =
+        serde_json::from_str(r#"{"success":false,"code":"brand_new","message":null,"extra":null}"#)
+            .unwrap();
+    assert_eq!(reply.code, Some(ErrorCode::Unknown));
+}
+
+#[test]
+fn reply_without_code_still_decodes() {
+    let reply: MessageReply =
+        serde_json::from_str(r#"{"success":true,"message":null,"extra":null}"#).unwrap();
+    assert_eq!(reply.code, None);
+}
@@ -0,0 +1,289 @@
+mod support;
+
+use std::time::Duration;
+
+use sine_chat::{
+    handler::Config,
+    message::{
+        ClientMessage, Content, DeleteMessage, EditMessage, ErrorCode, HistoryReply,
+        HistoryRequest, MessageDeleted, MessageEdited, MessageReply, ServerMessage,
+    },
+};
+use tokio::time;
+
+use support::{chat, history, text, TestClient, TestServer};
+
+#[tokio::test]
+async fn edits_messages() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let message = chat(&mut alice, &mut bob, text("helo")).await;
+    assert!(message.edited_at.is_none());
+
+    let edit = EditMessage {
+        id: message.id,
+        content: text("hello"),
+    };
+    alice.send(edit).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    for client in [&mut alice, &mut bob] {
+        let edited = client.expect::<MessageEdited>().await;
+        assert_eq!(edited.id, message.id);
+        assert_eq!(edited.content.to_string(), "hello");
+    }
+
+    let messages = history(&mut bob, "alice").await.messages;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].content.to_string(), "hello");
+    assert!(messages[0].edited_at.is_some());
+}
+
+#[tokio::test]
+async fn only_senders_change_messages() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let mut carol = server.login("carol").await;
+    let message = chat(&mut alice, &mut bob, text("hi")).await;
+
+    bob.send(DeleteMessage { id: message.id }).await;
+    let reply = bob.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::Forbidden));
+
+    let edit = EditMessage {
+        id: message.id,
+        content: text("hijacked"),
+    };
+    carol.send(edit).await;
+    let reply = carol.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::MessageNotFound));
+
+    let image = Content::Image {
+        url: "https://example.com/cat.png".into(),
+        width: 1.0,
+        height: 1.0,
+        thumbnails: Vec::new(),
+    };
+    let edit = EditMessage {
+        id: message.id,
+        content: image,
+    };
+    alice.send(edit).await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::Forbidden));
+
+    assert_eq!(
+        history(&mut alice, "bob").await.messages[0]
+            .content
+            .to_string(),
+        "hi"
+    );
+    assert!(history(&mut carol, "alice").await.messages.is_empty());
+}
+
+#[tokio::test]
+async fn recalls_messages_within_window() {
+    let server = TestServer::start_with_config(Config {
+        recall_window: Duration::from_millis(200),
+        ..Default::default()
+    })
+    .await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let first = chat(&mut alice, &mut bob, text("oops")).await;
+    let second = chat(&mut alice, &mut bob, text("fine")).await;
+
+    alice.send(DeleteMessage { id: first.id }).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    for client in [&mut alice, &mut bob] {
+        assert_eq!(client.expect::<MessageDeleted>().await.id, first.id);
+    }
+    let messages = history(&mut bob, "alice").await.messages;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].id, second.id);
+
+    time::sleep(Duration::from_millis(300)).await;
+    alice.send(DeleteMessage { id: second.id }).await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::RecallExpired));
+}
+
+#[tokio::test]
+async fn persists_latest_state() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        history_path: Some(dir.path().join("history.jsonl")),
+        ..Default::default()
+    };
+
+    let server = TestServer::start_with_config(config.clone()).await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let first = chat(&mut alice, &mut bob, text("one")).await;
+    let second = chat(&mut alice, &mut bob, text("two")).await;
+    let edit = EditMessage {
+        id: first.id,
+        content: text("uno"),
+    };
+    alice.send(edit).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect::<MessageEdited>().await;
+    alice.send(DeleteMessage { id: second.id }).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect::<MessageDeleted>().await;
+
+    // A fresh server on the same log stands in for a restart.
+    server.stop().await;
+    let server = TestServer::start_with_config(config).await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let messages = history(&mut alice, "bob").await.messages;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].content.to_string(), "uno");
+    assert!(messages[0].edited_at.is_some());
+
+    let third = chat(&mut bob, &mut alice, text("three")).await;
+    assert!(third.id > second.id);
+}
+
+#[tokio::test]
+async fn scrubs_recalled_messages_from_disk() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("history.jsonl");
+    let config = Config {
+        history_path: Some(path.clone()),
+        history_scrub_delay: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let server = TestServer::start_with_config(config).await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let kept = chat(&mut alice, &mut bob, text("kept")).await;
+    let message = chat(&mut alice, &mut bob, text("first draft")).await;
+    let edit = EditMessage {
+        id: message.id,
+        content: text("second draft"),
+    };
+    alice.send(edit).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect::<MessageEdited>().await;
+
+    alice.send(DeleteMessage { id: message.id }).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect::<MessageDeleted>().await;
+    time::sleep(Duration::from_millis(400)).await;
+    let log = std::fs::read_to_string(&path).unwrap();
+    assert!(!log.contains("draft"), "Recalled content on disk: {}", log);
+    assert_eq!(history(&mut alice, "bob").await.messages[0].id, kept.id);
+}
+
+/// Sends `text` in reply to `reply_to`, returns the reply to the sender.
+async fn reply(sender: &mut TestClient, receiver: &str, reply_to: u64, text: &str) -> MessageReply {
+    let mut message = ClientMessage::new(Content::Text(text.into()), receiver.into());
+    message.reply_to = Some(reply_to);
+    sender.send(message).await;
+    sender.expect::<MessageReply>().await
+}
+
+async fn thread(client: &mut TestClient, peer: &str, root: u64, before: Option<u64>) -> Vec<u64> {
+    let request = HistoryRequest {
+        peer: peer.into(),
+        before,
+        limit: 2,
+        thread: Some(root),
+    };
+    client.send(request).await;
+    let reply = client.expect::<HistoryReply>().await;
+    reply.messages.iter().map(|message| message.id).collect()
+}
+
+#[tokio::test]
+async fn replies_within_conversation() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let mut carol = server.login("carol").await;
+    let message = chat(&mut alice, &mut bob, text("lunch?")).await;
+
+    assert!(reply(&mut bob, "alice", message.id, "sure").await.success);
+    bob.expect::<ServerMessage>().await;
+    let delivered = alice.expect::<ServerMessage>().await;
+    assert_eq!(delivered.reply_to, Some(message.id));
+
+    for reply_to in [message.id, message.id + 100] {
+        let reply = reply(&mut carol, "alice", reply_to, "me too").await;
+        assert_eq!(reply.code, Some(ErrorCode::MessageNotFound));
+    }
+}
+
+#[tokio::test]
+async fn pages_through_threads() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let mut carol = server.login("carol").await;
+    let root = chat(&mut alice, &mut bob, text("lunch?")).await.id;
+    chat(&mut alice, &mut bob, text("unrelated")).await;
+
+    let mut replies = Vec::new();
+    for text in ["sure", "where?", "noon"] {
+        // The second one replies to the first, still in the thread.
+        let reply_to = if text == "where?" { replies[0] } else { root };
+        assert!(reply(&mut bob, "alice", reply_to, text).await.success);
+        replies.push(bob.expect::<ServerMessage>().await.id);
+        alice.expect::<ServerMessage>().await;
+    }
+
+    assert_eq!(thread(&mut alice, "bob", root, None).await, replies[1..]);
+    let before = Some(replies[1]);
+    assert_eq!(thread(&mut alice, "bob", root, before).await, replies[..1]);
+    assert!(thread(&mut carol, "alice", root, None).await.is_empty());
+}
+
+#[tokio::test]
+async fn pages_within_payload_limit() {
+    let server = TestServer::start_with_config(Config {
+        max_payload_length: 2048,
+        ..Default::default()
+    })
+    .await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let mut sent = Vec::new();
+    for i in 0..10 {
+        let content = text(&format!("{} {}", i, "x".repeat(300)));
+        sent.push(chat(&mut alice, &mut bob, content).await.id);
+    }
+
+    // Pages cut short to fit still cover the whole conversation.
+    let (mut received, mut before) = (Vec::new(), None);
+    loop {
+        let request = HistoryRequest {
+            peer: "alice".into(),
+            before,
+            limit: 10,
+            thread: None,
+        };
+        bob.send(request).await;
+        let reply = bob.expect::<HistoryReply>().await;
+        assert!(!reply.messages.is_empty() && reply.messages.len() < 10);
+        before = reply.messages.first().map(|message| message.id);
+        received.splice(0..0, reply.messages.iter().map(|message| message.id));
+        if !reply.has_more {
+            break;
+        }
+    }
+    assert_eq!(received, sent);
+
+    let request = HistoryRequest {
+        peer: "alice".into(),
+        before: None,
+        limit: 2,
+        thread: None,
+    };
+    bob.send(request).await;
+    let reply = bob.expect::<HistoryReply>().await;
+    assert_eq!(reply.messages.len(), 2);
+    assert!(reply.has_more);
+}
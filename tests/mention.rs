@@ -0,0 +1,66 @@
+mod support;
+
+use std::time::Duration;
+
+use sine_chat::message::{Acknowledge, Content, Mention, MentionSpan, Receipt, ReceiptKind};
+
+use support::{chat, history, text, TestServer};
+
+/// `text` mentioning `user` at the start of it.
+fn mentioning(user: &str, text: &str) -> Content {
+    Content::Mentions {
+        text: format!("@{} {}", user, text),
+        mentions: vec![MentionSpan {
+            user: user.into(),
+            start: 0,
+            end: user.len() + 1,
+        }],
+    }
+}
+
+#[tokio::test]
+async fn notifies_mentioned_users() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    let first = chat(&mut alice, &mut bob, mentioning("bob", "lunch?"))
+        .await
+        .id;
+    let mention = bob.expect::<Mention>().await;
+    assert_eq!((mention.id, mention.sender.as_str()), (first, "alice"));
+    assert_eq!(mention.unread_mentions, 1);
+
+    chat(&mut alice, &mut bob, text("no mention here")).await;
+    chat(&mut alice, &mut bob, mentioning("carol", "not you")).await;
+    bob.expect_silence(Duration::from_millis(100)).await;
+
+    chat(&mut alice, &mut bob, mentioning("bob", "noon?")).await;
+    assert_eq!(bob.expect::<Mention>().await.unread_mentions, 2);
+}
+
+#[tokio::test]
+async fn read_receipts_clear_mentions() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let first = chat(&mut alice, &mut bob, mentioning("bob", "lunch?"))
+        .await
+        .id;
+    bob.expect::<Mention>().await;
+    let second = chat(&mut alice, &mut bob, mentioning("bob", "noon?"))
+        .await
+        .id;
+    bob.expect::<Mention>().await;
+    assert_eq!(history(&mut bob, "alice").await.unread_mentions, 2);
+
+    for (id, left) in [(first, 1), (second, 0)] {
+        let read = Acknowledge {
+            kind: ReceiptKind::Read,
+            id,
+        };
+        bob.send(read).await;
+        alice.expect::<Receipt>().await;
+        assert_eq!(history(&mut bob, "alice").await.unread_mentions, left);
+    }
+}
@@ -0,0 +1,221 @@
+mod support;
+
+use std::time::Duration;
+
+use sine_chat::{
+    frame::ReceivableSerdePayload,
+    handler::Config,
+    message::{
+        Capability, ClientInfo, ClientMessage, Content, ErrorCode, Handshake, HandshakeReply,
+        MessageReply, Ping, Pong, Typing, TypingState, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+    },
+};
+
+use support::{text, TestServer, TIMEOUT};
+
+#[tokio::test]
+async fn delivers_message_to_both_sides() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    alice
+        .send(ClientMessage::new(text("hello"), "bob".into()))
+        .await;
+
+    let reply = alice.expect::<MessageReply>().await;
+    assert!(reply.success);
+    let echoed = alice.expect_message_from("alice", TIMEOUT).await;
+    assert_eq!(echoed.receiver, "bob");
+
+    let message = bob.expect_message_from("alice", TIMEOUT).await;
+    assert_eq!(message.receiver, "bob");
+    assert!(matches!(message.content, Content::Text(ref text) if text == "hello"));
+}
+
+#[tokio::test]
+async fn closes_connection_without_handshake() {
+    let server = TestServer::start_with_config(Config {
+        handshake_timeout: Duration::from_millis(100),
+        ..Default::default()
+    })
+    .await;
+    let mut client = server.connect().await;
+    client.expect_closed(TIMEOUT).await;
+}
+
+#[tokio::test]
+async fn rejects_duplicate_user() {
+    let server = TestServer::start().await;
+    let _alice = server.login("alice").await;
+
+    let mut duplicate = server.connect().await;
+    let reply = duplicate.handshake("alice").await;
+    assert!(!reply.success);
+    assert_eq!(reply.code, Some(ErrorCode::UserExists));
+    duplicate.expect_closed(TIMEOUT).await;
+}
+
+#[tokio::test]
+async fn replies_failure_for_unknown_receiver() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+
+    alice
+        .send(ClientMessage::new(text("hello"), "nobody".into()))
+        .await;
+
+    let reply = alice.expect::<MessageReply>().await;
+    assert!(!reply.success);
+    assert_eq!(reply.code, Some(ErrorCode::ReceiverNotFound));
+    alice.expect_silence(Duration::from_millis(100)).await;
+}
+
+#[tokio::test]
+async fn answers_ping_with_pong() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+
+    alice.send(Ping).await;
+    alice.expect::<Pong>().await;
+}
+
+#[tokio::test]
+async fn replies_failure_for_malformed_payload() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+
+    alice.send_raw(0x01, b"{ not json").await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert!(!reply.success);
+    assert_eq!(reply.code, Some(ErrorCode::MalformedPayload));
+    assert!(reply.message.is_some());
+
+    // The connection stays usable afterwards.
+    alice.send(Ping).await;
+    alice.expect::<Pong>().await;
+}
+
+#[tokio::test]
+async fn skips_oversized_frame() {
+    let server = TestServer::start_with_config(Config {
+        max_payload_length: 1024,
+        ..Default::default()
+    })
+    .await;
+    let mut alice = server.login("alice").await;
+
+    let message = ClientMessage::new(text(&"a".repeat(2048)), "bob".into());
+    alice.send(message).await;
+    alice.send(Ping).await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::TooLarge));
+    alice.expect::<Pong>().await;
+}
+
+#[tokio::test]
+async fn replies_failure_for_unexpected_type() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+
+    alice.send_raw(0x02, b"{}").await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert!(!reply.success);
+    assert_eq!(reply.code, Some(ErrorCode::TypeMismatch));
+}
+
+#[tokio::test]
+async fn rejects_malformed_handshake() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    client.send_raw(0x00, b"[]").await;
+    let reply = client.expect::<HandshakeReply>().await;
+    assert!(!reply.success);
+    assert_eq!(reply.code, Some(ErrorCode::MalformedPayload));
+    client.expect_closed(TIMEOUT).await;
+}
+
+#[tokio::test]
+async fn rejects_second_handshake() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+
+    alice.send(Handshake::new("mallory".into())).await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::TypeMismatch));
+}
+
+#[tokio::test]
+async fn negotiates_protocol_version_and_announces_limits() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let mut handshake = Handshake::new("alice".into());
+    handshake.version = PROTOCOL_VERSION + 10;
+    handshake.client = Some(ClientInfo::new("future".into(), "9.0".into()));
+    handshake.capabilities = vec![Capability::Unknown, Capability::Typing, Capability::Blobs];
+    let reply = client.handshake_with(handshake).await;
+    assert!(reply.success);
+    assert_eq!(reply.version, PROTOCOL_VERSION);
+    assert_eq!(
+        reply.capabilities,
+        vec![Capability::Blobs, Capability::Typing]
+    );
+    let limits = reply.limits.unwrap();
+    let config = Config::default();
+    assert_eq!(limits.max_payload_length, config.max_payload_length);
+    assert_eq!(limits.rate_limits[0].type_code, None);
+    let message_limit = limits
+        .rate_limits
+        .iter()
+        .find(|limit| limit.type_code == Some(ClientMessage::type_code()))
+        .unwrap();
+    let rate = config
+        .user_rate_limits
+        .rate(Some(ClientMessage::type_code()));
+    assert_eq!(message_limit.burst, rate.burst);
+    assert_eq!(message_limit.per_second, rate.per_second);
+}
+
+#[tokio::test]
+async fn skips_events_of_capabilities_not_negotiated() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.connect().await;
+    let mut handshake = Handshake::new("bob".into());
+    handshake.capabilities = vec![Capability::Reactions];
+    assert!(bob.handshake_with(handshake).await.success);
+
+    let typing = Typing {
+        receiver: "bob".into(),
+        state: TypingState::Typing,
+    };
+    alice.send(typing).await;
+    bob.expect_silence(Duration::from_millis(100)).await;
+}
+
+#[tokio::test]
+async fn rejects_unsupported_protocol_version() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    let mut handshake = Handshake::new("alice".into());
+    handshake.version = MIN_PROTOCOL_VERSION - 1;
+    let reply = client.handshake_with(handshake).await;
+    assert!(!reply.success);
+    assert_eq!(reply.code, Some(ErrorCode::UnsupportedVersion));
+    assert_eq!(reply.version, PROTOCOL_VERSION);
+    client.expect_closed(TIMEOUT).await;
+}
+
+#[tokio::test]
+async fn accepts_legacy_handshake() {
+    let server = TestServer::start().await;
+    let mut client = server.connect().await;
+
+    client.send_raw(0x00, br#"{"token":"alice"}"#).await;
+    let reply = client.expect::<HandshakeReply>().await;
+    assert!(reply.success);
+    assert_eq!(reply.version, 1);
+}
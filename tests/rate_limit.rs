@@ -0,0 +1,108 @@
+mod support;
+
+use std::{collections::HashMap, time::Duration};
+
+use sine_chat::{
+    frame::ReceivableSerdePayload,
+    handler::{Config, Rate, RateLimits},
+    message::{ClientMessage, Content, MessageReply, Ping, Pong, RateLimited, ServerMessage},
+};
+
+use support::{TestClient, TestServer};
+
+/// Limits of `message_rate` on `ClientMessage`, and generous ones on anything else.
+fn limits(message_rate: Rate) -> RateLimits {
+    RateLimits {
+        default: Rate::new(100, 100.0),
+        by_type: HashMap::from([(ClientMessage::type_code(), message_rate)]),
+    }
+}
+
+fn unlimited() -> RateLimits {
+    limits(Rate::new(100, 100.0))
+}
+
+async fn message(client: &mut TestClient, receiver: &str) {
+    let content = Content::Text("spam".into());
+    client
+        .send(ClientMessage::new(content, receiver.into()))
+        .await;
+}
+
+async fn expect_sent(client: &mut TestClient) {
+    assert!(client.expect::<MessageReply>().await.success);
+    client.expect::<ServerMessage>().await;
+}
+
+#[tokio::test]
+async fn limits_each_user_by_type() {
+    let server = TestServer::start_with_config(Config {
+        user_rate_limits: limits(Rate::new(2, 0.5)),
+        ip_rate_limits: unlimited(),
+        ..Default::default()
+    })
+    .await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    for _ in 0..2 {
+        message(&mut alice, "bob").await;
+        expect_sent(&mut alice).await;
+        bob.expect::<ServerMessage>().await;
+    }
+    message(&mut alice, "bob").await;
+    let limited = alice.expect::<RateLimited>().await;
+    assert_eq!(limited.type_code, Some(ClientMessage::type_code()));
+    assert!(limited.retry_after > 1000 && limited.retry_after <= 2000);
+
+    // Other types and other users have buckets of their own.
+    alice.send(Ping).await;
+    alice.expect::<Pong>().await;
+    message(&mut bob, "alice").await;
+    expect_sent(&mut bob).await;
+}
+
+#[tokio::test]
+async fn shares_limits_across_an_ip() {
+    let server = TestServer::start_with_config(Config {
+        user_rate_limits: unlimited(),
+        ip_rate_limits: limits(Rate::new(3, 0.1)),
+        ..Default::default()
+    })
+    .await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let _carol = server.login("carol").await;
+
+    for _ in 0..2 {
+        message(&mut alice, "carol").await;
+        expect_sent(&mut alice).await;
+    }
+    message(&mut bob, "carol").await;
+    expect_sent(&mut bob).await;
+    message(&mut bob, "carol").await;
+    let limited = bob.expect::<RateLimited>().await;
+    assert_eq!(limited.type_code, Some(ClientMessage::type_code()));
+}
+
+#[tokio::test]
+async fn disconnects_persistent_floods() {
+    let server = TestServer::start_with_config(Config {
+        user_rate_limits: limits(Rate::new(1, 0.1)),
+        ip_rate_limits: unlimited(),
+        rate_violations: Rate::new(3, 0.1),
+        ..Default::default()
+    })
+    .await;
+    let mut alice = server.login("alice").await;
+    let _bob = server.login("bob").await;
+
+    message(&mut alice, "bob").await;
+    expect_sent(&mut alice).await;
+    for _ in 0..3 {
+        message(&mut alice, "bob").await;
+        alice.expect::<RateLimited>().await;
+    }
+    message(&mut alice, "bob").await;
+    alice.expect_closed(Duration::from_secs(1)).await;
+}
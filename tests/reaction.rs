@@ -0,0 +1,118 @@
+mod support;
+
+use std::time::Duration;
+
+use sine_chat::message::{
+    AddReaction, ClientMessage, Content, ErrorCode, HistoryReply, HistoryRequest, MessageReply,
+    ReactionUpdated, RemoveReaction, ServerMessage,
+};
+
+use support::{TestClient, TestServer};
+
+async fn start() -> (TestClient, TestClient, ServerMessage) {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let content = Content::Text("lunch?".into());
+    alice.send(ClientMessage::new(content, "bob".into())).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect::<ServerMessage>().await;
+    let message = bob.expect::<ServerMessage>().await;
+    (alice, bob, message)
+}
+
+async fn react(client: &mut TestClient, id: u64, emoji: &str) -> MessageReply {
+    let add = AddReaction {
+        id,
+        emoji: emoji.into(),
+    };
+    client.send(add).await;
+    client.expect::<MessageReply>().await
+}
+
+/// Expects both sides to see the update, returns the reactions as (emoji, count).
+async fn expect_update(
+    alice: &mut TestClient,
+    bob: &mut TestClient,
+    id: u64,
+) -> Vec<(String, usize)> {
+    let update = alice.expect::<ReactionUpdated>().await;
+    assert_eq!(
+        bob.expect::<ReactionUpdated>().await.reactions,
+        update.reactions
+    );
+    assert_eq!(update.id, id);
+    update
+        .reactions
+        .into_iter()
+        .map(|r| (r.emoji, r.count))
+        .collect()
+}
+
+#[tokio::test]
+async fn aggregates_reactions() {
+    let (mut alice, mut bob, message) = start().await;
+
+    assert!(react(&mut bob, message.id, "👍").await.success);
+    assert_eq!(
+        expect_update(&mut alice, &mut bob, message.id).await,
+        [("👍".into(), 1)]
+    );
+    assert!(react(&mut alice, message.id, "👍").await.success);
+    expect_update(&mut alice, &mut bob, message.id).await;
+    assert!(react(&mut alice, message.id, "🎉").await.success);
+    assert_eq!(
+        expect_update(&mut alice, &mut bob, message.id).await,
+        [("👍".into(), 2), ("🎉".into(), 1)]
+    );
+
+    let remove = RemoveReaction {
+        id: message.id,
+        emoji: "👍".into(),
+    };
+    bob.send(remove).await;
+    assert!(bob.expect::<MessageReply>().await.success);
+    assert_eq!(
+        expect_update(&mut alice, &mut bob, message.id).await,
+        [("👍".into(), 1), ("🎉".into(), 1)]
+    );
+
+    let request = HistoryRequest {
+        peer: "alice".into(),
+        before: None,
+        limit: 10,
+        thread: None,
+    };
+    bob.send(request).await;
+    let reactions = &bob.expect::<HistoryReply>().await.messages[0].reactions;
+    assert_eq!(reactions[0].users, ["alice"]);
+    assert_eq!(reactions[1].emoji, "🎉");
+}
+
+#[tokio::test]
+async fn ignores_repeated_reactions() {
+    let (mut alice, mut bob, message) = start().await;
+    assert!(react(&mut bob, message.id, "❤️").await.success);
+    expect_update(&mut alice, &mut bob, message.id).await;
+
+    assert!(react(&mut bob, message.id, "❤️").await.success);
+    let remove = RemoveReaction {
+        id: message.id,
+        emoji: "😮".into(),
+    };
+    bob.send(remove).await;
+    assert!(bob.expect::<MessageReply>().await.success);
+    alice.expect_silence(Duration::from_millis(100)).await;
+    bob.expect_silence(Duration::from_millis(100)).await;
+}
+
+#[tokio::test]
+async fn rejects_invalid_reactions() {
+    let (_alice, mut bob, message) = start().await;
+    for emoji in ["", "lol", "👍 👍", &"👍".repeat(20)] {
+        let reply = react(&mut bob, message.id, emoji).await;
+        assert_eq!(reply.code, Some(ErrorCode::MalformedPayload), "{:?}", emoji);
+    }
+    let reply = react(&mut bob, message.id + 1, "👍").await;
+    assert_eq!(reply.code, Some(ErrorCode::MessageNotFound));
+}
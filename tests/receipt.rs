@@ -0,0 +1,103 @@
+mod support;
+
+use std::time::Duration;
+
+use sine_chat::{
+    handler::Config,
+    message::{Acknowledge, ErrorCode, MessageReply, Receipt, ReceiptKind},
+};
+
+use support::{chat, history, text, TestServer};
+
+#[tokio::test]
+async fn relays_receipts_to_sender() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let first = chat(&mut alice, &mut bob, text("one")).await;
+    let second = chat(&mut alice, &mut bob, text("two")).await;
+
+    let delivered = Acknowledge {
+        kind: ReceiptKind::Delivered,
+        id: first.id,
+    };
+    bob.send(delivered).await;
+    let receipt = alice.expect::<Receipt>().await;
+    assert_eq!(receipt.kind, ReceiptKind::Delivered);
+    assert_eq!(receipt.id, first.id);
+    assert_eq!(receipt.by, "bob");
+
+    let read = Acknowledge {
+        kind: ReceiptKind::Read,
+        id: second.id,
+    };
+    bob.send(read).await;
+    let receipt = alice.expect::<Receipt>().await;
+    assert_eq!(receipt.kind, ReceiptKind::Read);
+    assert_eq!(receipt.id, second.id);
+
+    // Reading behind the current position isn't news.
+    let read = Acknowledge {
+        kind: ReceiptKind::Read,
+        id: first.id,
+    };
+    bob.send(read).await;
+    alice.expect_silence(Duration::from_millis(100)).await;
+    bob.expect_silence(Duration::from_millis(100)).await;
+}
+
+#[tokio::test]
+async fn only_receivers_acknowledge() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let mut carol = server.login("carol").await;
+    let message = chat(&mut alice, &mut bob, text("hi")).await;
+
+    for client in [&mut alice, &mut carol] {
+        let read = Acknowledge {
+            kind: ReceiptKind::Read,
+            id: message.id,
+        };
+        client.send(read).await;
+        let reply = client.expect::<MessageReply>().await;
+        assert_eq!(reply.code, Some(ErrorCode::MessageNotFound));
+    }
+    assert_eq!(history(&mut bob, "alice").await.unread, 1);
+}
+
+#[tokio::test]
+async fn counts_unread_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        history_path: Some(dir.path().join("history.jsonl")),
+        ..Default::default()
+    };
+
+    let server = TestServer::start_with_config(config.clone()).await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    chat(&mut alice, &mut bob, text("one")).await;
+    let second = chat(&mut alice, &mut bob, text("two")).await;
+    chat(&mut alice, &mut bob, text("three")).await;
+    chat(&mut bob, &mut alice, text("reply")).await;
+
+    let reply = history(&mut bob, "alice").await;
+    assert_eq!((reply.read_up_to, reply.unread), (0, 3));
+    let read = Acknowledge {
+        kind: ReceiptKind::Read,
+        id: second.id,
+    };
+    bob.send(read).await;
+    alice.expect::<Receipt>().await;
+
+    // A fresh server on the same log stands in for a restart.
+    let server = TestServer::start_with_config(config).await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let reply = history(&mut bob, "alice").await;
+    assert_eq!((reply.read_up_to, reply.unread), (second.id, 1));
+    let reply = history(&mut alice, "bob").await;
+    assert_eq!(reply.peer_read_up_to, second.id);
+    assert_eq!((reply.read_up_to, reply.unread), (0, 1));
+}
@@ -0,0 +1,48 @@
+use sine_chat::frame::{
+    messages::{self, client},
+    registry::{self, Direction, PayloadInfo},
+    ReceivableEnum,
+};
+
+#[test]
+fn readme_protocol_table_is_up_to_date() {
+    let table = registry::protocol_table(&registry::payloads());
+    let readme = include_str!("../README.md");
+    assert!(
+        readme.contains(&table),
+        "README is out of date, regenerate the table with `cargo run --example protocol_table`:\n{}",
+        table
+    );
+}
+
+#[test]
+fn registry_covers_both_directions() {
+    let registry = registry::payloads();
+    let handshake = registry.iter().find(|p| p.name == "Handshake").unwrap();
+    assert_eq!(handshake.type_code, 0x00);
+    assert_eq!(handshake.direction, Direction::ClientToServer);
+
+    let reply = registry.iter().find(|p| p.name == "MessageReply").unwrap();
+    assert_eq!(reply.direction, Direction::ServerToClient);
+}
+
+#[test]
+fn inbound_enums_cover_the_registry() {
+    let sorted = |payloads: &[PayloadInfo]| {
+        let mut names: Vec<_> = payloads.iter().map(|p| p.name).collect();
+        names.sort_unstable();
+        names
+    };
+    let registry = registry::payloads();
+    for (direction, inbound) in [
+        (Direction::ClientToServer, messages::Inbound::PAYLOADS),
+        (Direction::ServerToClient, client::Inbound::PAYLOADS),
+    ] {
+        let registered: Vec<_> = registry
+            .iter()
+            .filter(|p| p.direction == direction)
+            .copied()
+            .collect();
+        assert_eq!(sorted(inbound), sorted(&registered), "{:?}", direction);
+    }
+}
@@ -0,0 +1,243 @@
+mod support;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use sine_chat::{
+    handler::Config,
+    message::{
+        CancelScheduled, ClientMessage, Content, DownloadChunk, DownloadRequest, ErrorCode,
+        ListScheduled, MessageReply, ScheduleMessage, ScheduledList, ServerMessage, UploadChunk,
+        UploadReply, UploadRequest,
+    },
+};
+
+use support::{TestClient, TestServer};
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Schedules `text` to bob `delay` from now, returns the reply.
+async fn schedule(client: &mut TestClient, text: &str, delay: Duration) -> MessageReply {
+    let schedule = ScheduleMessage {
+        deliver_at: now_millis() + delay.as_millis() as u64,
+        message: ClientMessage::new(Content::Text(text.into()), "bob".into()),
+    };
+    client.send(schedule).await;
+    client.expect::<MessageReply>().await
+}
+
+async fn list(client: &mut TestClient) -> Vec<u64> {
+    client.send(ListScheduled).await;
+    let list = client.expect::<ScheduledList>().await;
+    list.messages.iter().map(|message| message.id).collect()
+}
+
+#[tokio::test]
+async fn delivers_when_due() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    let later = schedule(&mut alice, "later", Duration::from_millis(600)).await;
+    let sooner = schedule(&mut alice, "sooner", Duration::from_millis(300)).await;
+    let id = |reply: &MessageReply| {
+        reply.extra.as_ref().unwrap()["schedule_id"]
+            .as_u64()
+            .unwrap()
+    };
+    assert_eq!(list(&mut alice).await, [id(&sooner), id(&later)]);
+    bob.expect_silence(Duration::from_millis(200)).await;
+
+    for text in ["sooner", "later"] {
+        let message = bob
+            .expect_message_from("alice", Duration::from_secs(2))
+            .await;
+        assert_eq!(message.content.to_string(), text);
+        assert!(alice.expect::<MessageReply>().await.success);
+        alice.expect::<ServerMessage>().await;
+    }
+    assert!(list(&mut alice).await.is_empty());
+}
+
+#[tokio::test]
+async fn cancels_scheduled_messages() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    let reply = schedule(&mut alice, "never", Duration::from_millis(300)).await;
+    let id = reply.extra.unwrap()["schedule_id"].as_u64().unwrap();
+    bob.send(CancelScheduled { id }).await;
+    let reply = bob.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::MessageNotFound));
+    alice.send(CancelScheduled { id }).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    assert!(list(&mut alice).await.is_empty());
+    bob.expect_silence(Duration::from_millis(500)).await;
+
+    let schedule = ScheduleMessage {
+        deliver_at: now_millis() - 1000,
+        message: ClientMessage::new(Content::Text("too late".into()), "bob".into()),
+    };
+    alice.send(schedule).await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::MalformedPayload));
+}
+
+#[tokio::test]
+async fn retries_offline_receivers() {
+    let server = TestServer::start_with_config(Config {
+        schedule_retry: Duration::from_millis(300),
+        ..Default::default()
+    })
+    .await;
+    let mut alice = server.login("alice").await;
+    let reply = schedule(&mut alice, "are you there", Duration::from_millis(200)).await;
+    let id = reply.extra.unwrap()["schedule_id"].as_u64().unwrap();
+
+    // The first miss is reported, the message stays listed with it.
+    let reply = alice
+        .expect_within::<MessageReply>(Duration::from_secs(2))
+        .await;
+    assert_eq!(reply.code, Some(ErrorCode::ReceiverNotFound));
+    assert_eq!(reply.extra.unwrap()["schedule_id"], id);
+    alice.send(ListScheduled).await;
+    let scheduled = &alice.expect::<ScheduledList>().await.messages[0];
+    assert_eq!(scheduled.id, id);
+    assert_eq!(scheduled.error, Some(ErrorCode::ReceiverNotFound));
+    alice.expect_silence(Duration::from_millis(700)).await;
+
+    let mut bob = server.login("bob").await;
+    let message = bob
+        .expect_message_from("alice", Duration::from_secs(2))
+        .await;
+    assert_eq!(message.content.to_string(), "are you there");
+    let reply = alice.expect::<MessageReply>().await;
+    assert!(reply.success);
+    assert_eq!(reply.extra.unwrap()["schedule_id"], id);
+    alice.expect::<ServerMessage>().await;
+    assert!(list(&mut alice).await.is_empty());
+}
+
+#[tokio::test]
+async fn keeps_failed_messages() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let mut message = ClientMessage::new(Content::Text("re: nothing".into()), "bob".into());
+    message.reply_to = Some(404);
+    alice
+        .send(ScheduleMessage {
+            deliver_at: now_millis() + 200,
+            message,
+        })
+        .await;
+    let id = alice.expect::<MessageReply>().await.extra.unwrap()["schedule_id"]
+        .as_u64()
+        .unwrap();
+
+    let reply = alice
+        .expect_within::<MessageReply>(Duration::from_secs(2))
+        .await;
+    assert_eq!(reply.code, Some(ErrorCode::MessageNotFound));
+    assert_eq!(reply.extra.unwrap()["schedule_id"], id);
+    bob.expect_silence(Duration::from_millis(300)).await;
+
+    // It's not tried again, but stays listed until cancelled.
+    alice.send(ListScheduled).await;
+    let scheduled = &alice.expect::<ScheduledList>().await.messages[0];
+    assert_eq!(scheduled.error, Some(ErrorCode::MessageNotFound));
+    alice.send(CancelScheduled { id }).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    assert!(list(&mut alice).await.is_empty());
+}
+
+#[tokio::test]
+async fn shares_blobs_when_sent() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = TestServer::start_with_config(Config {
+        blob_dir: dir.path().into(),
+        ..Default::default()
+    })
+    .await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let data = b"minutes of the meeting".to_vec();
+    alice
+        .send(UploadRequest {
+            transfer_id: None,
+            name: "minutes.txt".into(),
+            size: data.len() as u64,
+            mime: "text/plain".into(),
+            checksum: format!("{:x}", Sha256::digest(&data)),
+        })
+        .await;
+    let reply = alice.expect::<UploadReply>().await;
+    let chunk = UploadChunk::new(reply.transfer_id.unwrap(), 0, data.clone());
+    alice.send(chunk).await;
+    let blob_id = alice.expect::<UploadReply>().await.blob_id.unwrap();
+    let file = |blob_id: &str| Content::File {
+        blob_id: blob_id.into(),
+        name: "minutes.txt".into(),
+        size: data.len() as u64,
+        mime: "text/plain".into(),
+    };
+
+    // Blobs are checked when scheduling, like when sending.
+    let schedule_file = |blob_id: &str| ScheduleMessage {
+        deliver_at: now_millis() + 300,
+        message: ClientMessage::new(file(blob_id), "bob".into()),
+    };
+    alice.send(schedule_file(&"ab".repeat(32))).await;
+    let reply = alice.expect::<MessageReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::BlobNotFound));
+    alice.send(schedule_file(&blob_id)).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    let download = DownloadRequest {
+        blob_id: blob_id.clone(),
+        offset: 0,
+    };
+    bob.send(download.clone()).await;
+    assert_eq!(
+        bob.expect::<DownloadChunk>().await.code,
+        Some(ErrorCode::BlobNotFound)
+    );
+
+    // Sending it lets the receiver in.
+    bob.expect_message_from("alice", Duration::from_secs(2))
+        .await;
+    bob.send(download).await;
+    assert_eq!(bob.expect::<DownloadChunk>().await.data, data);
+}
+
+#[tokio::test]
+async fn delivers_after_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        schedule_path: Some(dir.path().join("schedule.json")),
+        ..Default::default()
+    };
+
+    let server = TestServer::start_with_config(config.clone()).await;
+    let mut alice = server.login("alice").await;
+    assert!(
+        schedule(&mut alice, "good morning", Duration::from_millis(500))
+            .await
+            .success
+    );
+
+    // A fresh server on the same file stands in for a restart, alice stays offline.
+    server.stop().await;
+    let server = TestServer::start_with_config(config).await;
+    let mut bob = server.login("bob").await;
+    let message = bob
+        .expect_message_from("alice", Duration::from_secs(2))
+        .await;
+    assert_eq!(message.content.to_string(), "good morning");
+}
@@ -0,0 +1,200 @@
+mod support;
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::BytesMut;
+use sine_chat::{
+    frame::{ClientSide, Codec, Error, RawPayload, Reader, ReceiveLimits, ServerSide, Writer},
+    handler::Config,
+    message::{ErrorCode, Handshake, HandshakeReply, MessageReply},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+    time::{self, Instant, Sleep},
+};
+use tokio_util::codec::Encoder;
+
+use support::TestServer;
+
+/// Hands out `data` a `chunk` at a time, one every `interval`, then stalls.
+struct SlowStream {
+    data: Vec<u8>,
+    chunk: usize,
+    interval: Duration,
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl SlowStream {
+    fn new(data: Vec<u8>, chunk: usize, interval: Duration) -> Self {
+        Self {
+            data,
+            chunk,
+            interval,
+            sleep: Box::pin(time::sleep(interval)),
+        }
+    }
+}
+
+impl AsyncRead for SlowStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.data.is_empty() || self.sleep.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let next = Instant::now() + self.interval;
+        self.sleep.as_mut().reset(next);
+        let len = self.chunk.min(self.data.len()).min(buf.remaining());
+        buf.put_slice(&self.data[..len]);
+        self.data.drain(..len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn frame(content: &[u8]) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    let payload = RawPayload::new(0x01, content.to_vec().into());
+    Codec::new().encode(payload, &mut buf).unwrap();
+    buf.to_vec()
+}
+
+fn reader(
+    stream: SlowStream,
+    timeout: Duration,
+    min_throughput: u64,
+) -> Reader<SlowStream, ServerSide> {
+    let mut reader = Reader::new(stream);
+    reader.set_receive_limits(ReceiveLimits {
+        timeout,
+        min_throughput,
+    });
+    reader
+}
+
+#[tokio::test]
+async fn reads_frames_arriving_in_time() {
+    let content = [7; 200];
+    let mut data = frame(&content);
+    data.extend(frame(b"next"));
+    let stream = SlowStream::new(data, 20, Duration::from_millis(10));
+    let mut reader = reader(stream, Duration::from_millis(500), 100);
+
+    let raw = reader.read_raw().await.unwrap().unwrap();
+    assert_eq!(raw.content, content[..]);
+    let raw = reader.read_raw().await.unwrap().unwrap();
+    assert_eq!(raw.content, b"next"[..]);
+}
+
+#[tokio::test]
+async fn waits_for_frames_without_limit() {
+    let data = frame(b"late");
+    let len = data.len();
+    let stream = SlowStream::new(data, len, Duration::from_millis(300));
+    let mut reader = reader(stream, Duration::from_millis(100), 100);
+    let raw = reader.read_raw().await.unwrap().unwrap();
+    assert_eq!(raw.content, b"late"[..]);
+}
+
+#[tokio::test]
+async fn fails_stalled_frames() {
+    let data = frame(b"never finished")[..3].to_vec();
+    let stream = SlowStream::new(data, 3, Duration::from_millis(10));
+    let mut reader = reader(stream, Duration::from_millis(200), 0);
+
+    let started = Instant::now();
+    assert!(matches!(
+        reader.read_raw().await,
+        Some(Err(Error::SlowFrame))
+    ));
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(reader.read_raw().await.is_none());
+}
+
+#[tokio::test]
+async fn fails_trickled_frames() {
+    // 50 bytes per second against a minimum of 200.
+    let stream = SlowStream::new(frame(&[7; 1000]), 1, Duration::from_millis(20));
+    let mut reader = reader(stream, Duration::from_secs(10), 200);
+
+    let started = Instant::now();
+    assert!(matches!(
+        reader.read_raw().await,
+        Some(Err(Error::SlowFrame))
+    ));
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[tokio::test]
+async fn times_frames_only_while_reading() {
+    let (mut peer, stream) = tokio::io::duplex(1024);
+    let mut reader = Reader::<_, ServerSide>::new(stream);
+    reader.set_receive_limits(ReceiveLimits {
+        timeout: Duration::from_millis(500),
+        min_throughput: 0,
+    });
+    let second = frame(b"second");
+    let mut data = frame(b"first");
+    data.extend(&second[..3]);
+    peer.write_all(&data).await.unwrap();
+    let raw = reader.read_raw().await.unwrap().unwrap();
+    assert_eq!(raw.content, b"first"[..]);
+
+    // The rest is there at once, while the reader is busy with the first frame.
+    peer.write_all(&second[3..]).await.unwrap();
+    time::sleep(Duration::from_millis(800)).await;
+    let raw = reader.read_raw().await.unwrap().unwrap();
+    assert_eq!(raw.content, b"second"[..]);
+}
+
+#[tokio::test]
+async fn closes_slow_connections() {
+    let server = TestServer::start_with_config(Config {
+        handshake_timeout: Duration::from_secs(60),
+        frame_timeout: Duration::from_millis(200),
+        ..Default::default()
+    })
+    .await;
+    let mut stream = TcpStream::connect(server.addr).await.unwrap();
+    stream.write_all(&frame(b"{}")[..3]).await.unwrap();
+
+    let mut rest = Vec::new();
+    let read = time::timeout(Duration::from_secs(1), stream.read_to_end(&mut rest)).await;
+    assert!(read.is_ok(), "Connection still open");
+}
+
+#[tokio::test]
+async fn tells_clients_before_closing() {
+    let server = TestServer::start_with_config(Config {
+        frame_timeout: Duration::from_millis(200),
+        ..Default::default()
+    })
+    .await;
+    let mut stream = TcpStream::connect(server.addr).await.unwrap();
+    let (read, mut write) = stream.split();
+    let mut reader = Reader::<_, ClientSide>::new(read);
+    let mut writer = Writer::<_, ClientSide>::new(&mut write);
+    writer.write(Handshake::new("alice".into())).await.unwrap();
+    let reply = reader.read::<HandshakeReply>().await.unwrap().unwrap();
+    assert!(reply.success);
+    reader.set_encoding(reply.encoding);
+    reader.set_compression(reply.compression);
+
+    write.write_all(&frame(b"{}")[..3]).await.unwrap();
+    let reply = time::timeout(Duration::from_secs(1), reader.read::<MessageReply>())
+        .await
+        .expect("No reply to the slow frame")
+        .unwrap()
+        .unwrap();
+    assert_eq!(reply.code, Some(ErrorCode::Timeout));
+    let closed = time::timeout(Duration::from_secs(1), reader.read_raw()).await;
+    assert!(matches!(closed, Ok(None)), "Connection still open");
+}
@@ -0,0 +1,229 @@
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, time::Duration};
+
+use sine_chat::{
+    frame::{self, ClientSide, Encoding, RawPayload, ReceivableBy, SendableBy, SendablePayload},
+    handler::Config,
+    message::{
+        ClientMessage, Content, Handshake, HandshakeReply, HistoryReply, HistoryRequest,
+        MessageReply, ServerMessage,
+    },
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::oneshot,
+    task::JoinHandle,
+    time,
+};
+
+pub const TIMEOUT: Duration = Duration::from_secs(1);
+
+type Reader = frame::Reader<tokio::net::tcp::OwnedReadHalf, ClientSide>;
+type Writer = frame::Writer<tokio::net::tcp::OwnedWriteHalf, ClientSide>;
+
+// Server
+
+/// A server on a free local port, running until stopped.
+pub struct TestServer {
+    pub addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<anyhow::Result<()>>,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        Self::start_with_config(Config::default()).await
+    }
+
+    pub async fn start_with_config(config: Config) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown, stopped) = oneshot::channel();
+        let stopped = async {
+            // Dropping the server leaves it running for the clients still around.
+            if stopped.await.is_err() {
+                std::future::pending().await
+            }
+        };
+        let task = tokio::spawn(sine_chat::serve_until(listener, config, stopped));
+        Self {
+            addr,
+            shutdown,
+            task,
+        }
+    }
+
+    /// Closes every connection and waits until nothing more is written, e.g. before a restart.
+    pub async fn stop(self) {
+        let _ = self.shutdown.send(());
+        self.task.await.unwrap().unwrap();
+    }
+
+    pub async fn connect(&self) -> TestClient {
+        TestClient::connect(self.addr).await
+    }
+
+    /// Connects and completes the handshake, panics on failure.
+    pub async fn login(&self, user_name: &str) -> TestClient {
+        let mut client = self.connect().await;
+        let reply = client.handshake(user_name).await;
+        assert!(
+            reply.success,
+            "Handshake of {} failed: {:?}",
+            user_name, reply
+        );
+        client
+    }
+}
+
+// Client
+
+pub struct TestClient {
+    pub user_name: Option<String>,
+    pub http_token: Option<String>,
+    reader: Reader,
+    writer: Writer,
+}
+
+impl TestClient {
+    pub async fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (reader, writer) = stream.into_split();
+        Self {
+            user_name: None,
+            http_token: None,
+            reader: Reader::new(reader),
+            writer: Writer::new(writer),
+        }
+    }
+
+    pub async fn handshake(&mut self, user_name: &str) -> HandshakeReply {
+        self.handshake_with(Handshake::new(user_name.into())).await
+    }
+
+    /// Sends `handshake` and applies the negotiated encoding on success.
+    pub async fn handshake_with(&mut self, handshake: Handshake) -> HandshakeReply {
+        let user_name = handshake.token.clone();
+        self.send(handshake).await;
+        let reply = self.expect::<HandshakeReply>().await;
+        if reply.success {
+            self.user_name = Some(user_name);
+            self.http_token = reply.http_token.clone();
+            self.reader.set_encoding(reply.encoding);
+            self.writer.set_encoding(reply.encoding);
+            self.reader.set_compression(reply.compression);
+            self.writer.set_compression(reply.compression);
+        }
+        reply
+    }
+
+    pub async fn send<P>(&mut self, payload: P)
+    where
+        P: SendableBy<ClientSide>,
+    {
+        self.writer.write(payload).await.unwrap();
+    }
+
+    pub async fn send_raw(&mut self, type_code: u8, content: &'static [u8]) {
+        self.send(RawFrame(type_code, content)).await;
+    }
+
+    /// Waits for the next frame, which must decode as `P`.
+    pub async fn expect<P>(&mut self) -> P
+    where
+        P: ReceivableBy<ClientSide>,
+    {
+        self.expect_within(TIMEOUT).await
+    }
+
+    pub async fn expect_within<P>(&mut self, timeout: Duration) -> P
+    where
+        P: ReceivableBy<ClientSide>,
+    {
+        match time::timeout(timeout, self.reader.read::<P>()).await {
+            Ok(Some(Ok(payload))) => payload,
+            Ok(Some(Err(err))) => panic!("Unexpected frame: {}", err),
+            Ok(None) => panic!("Connection closed"),
+            Err(_) => panic!("No frame received within {:?}", timeout),
+        }
+    }
+
+    /// Asserts that a `ServerMessage` sent by `sender` arrives within `timeout`.
+    pub async fn expect_message_from(&mut self, sender: &str, timeout: Duration) -> ServerMessage {
+        let message = self.expect_within::<ServerMessage>(timeout).await;
+        assert_eq!(message.sender, sender, "Unexpected sender: {:?}", message);
+        message
+    }
+
+    /// Asserts that nothing arrives within `timeout`.
+    pub async fn expect_silence(&mut self, timeout: Duration) {
+        if let Ok(frame) = time::timeout(timeout, self.reader.read_raw()).await {
+            panic!("Unexpected frame: {:?}", frame);
+        }
+    }
+
+    /// Asserts that the server closes the connection within `timeout`.
+    pub async fn expect_closed(&mut self, timeout: Duration) {
+        match time::timeout(timeout, self.reader.read_raw()).await {
+            Ok(None) | Ok(Some(Err(_))) => (),
+            Ok(Some(Ok(frame))) => panic!("Unexpected frame: {:?}", frame),
+            Err(_) => panic!("Connection still open after {:?}", timeout),
+        }
+    }
+}
+
+// Conversations
+
+pub fn text(text: &str) -> Content {
+    Content::Text(text.into())
+}
+
+/// Sends `content` from `sender` to `receiver`, returns the message as delivered.
+pub async fn chat(
+    sender: &mut TestClient,
+    receiver: &mut TestClient,
+    content: Content,
+) -> ServerMessage {
+    let receiver_name = receiver.user_name.clone().unwrap();
+    deliver(sender, receiver, ClientMessage::new(content, receiver_name)).await
+}
+
+/// Sends `message` from `sender` to `receiver`, returns it as delivered.
+pub async fn deliver(
+    sender: &mut TestClient,
+    receiver: &mut TestClient,
+    message: ClientMessage,
+) -> ServerMessage {
+    sender.send(message).await;
+    let reply = sender.expect::<MessageReply>().await;
+    assert!(reply.success, "Sending failed: {:?}", reply);
+    let echo = sender.expect::<ServerMessage>().await;
+    let message = receiver.expect::<ServerMessage>().await;
+    assert_eq!(echo.id, message.id);
+    assert_eq!(reply.extra.unwrap()["id"], message.id);
+    message
+}
+
+/// The latest page of the client's conversation with `peer`.
+pub async fn history(client: &mut TestClient, peer: &str) -> HistoryReply {
+    let request = HistoryRequest {
+        peer: peer.into(),
+        before: None,
+        limit: 50,
+        thread: None,
+    };
+    client.send(request).await;
+    client.expect::<HistoryReply>().await
+}
+
+/// Arbitrary bytes under a type code, for exercising the decode error paths.
+struct RawFrame(u8, &'static [u8]);
+
+impl SendablePayload for RawFrame {
+    fn as_raw(&self, _encoding: Encoding) -> frame::Result<RawPayload> {
+        Ok(RawPayload::new(self.0, self.1.into()))
+    }
+}
+
+impl SendableBy<ClientSide> for RawFrame {}
@@ -0,0 +1,271 @@
+mod support;
+
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+
+use sine_chat::{
+    frame::Encoding,
+    handler::Config,
+    message::{
+        ClientMessage, Content, DownloadChunk, DownloadRequest, ErrorCode, MessageReply,
+        ServerMessage, UploadChunk, UploadReply, UploadRequest,
+    },
+};
+use tempfile::TempDir;
+use tokio::time;
+
+use support::{TestClient, TestServer};
+
+async fn start() -> (TestServer, TempDir) {
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        max_payload_length: 1024,
+        blob_dir: dir.path().into(),
+        ..Default::default()
+    };
+    (TestServer::start_with_config(config).await, dir)
+}
+
+fn file(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn request(data: &[u8], transfer_id: Option<String>) -> UploadRequest {
+    UploadRequest {
+        transfer_id,
+        name: "notes.txt".into(),
+        size: data.len() as u64,
+        mime: "text/plain".into(),
+        checksum: format!("{:x}", Sha256::digest(data)),
+    }
+}
+
+/// Sends chunks from `reply.offset` until `limit`, returns the last reply.
+async fn send_chunks(
+    client: &mut TestClient,
+    data: &[u8],
+    mut reply: UploadReply,
+    limit: usize,
+) -> UploadReply {
+    let transfer_id = reply.transfer_id.clone().unwrap();
+    while reply.blob_id.is_none() && (reply.offset as usize) < limit {
+        let start = reply.offset as usize;
+        let end = (start + reply.chunk_size).min(limit);
+        let chunk = UploadChunk::new(transfer_id.clone(), reply.offset, data[start..end].to_vec());
+        client.send(chunk).await;
+        reply = client.expect::<UploadReply>().await;
+        assert!(reply.success, "Chunk rejected: {:?}", reply);
+    }
+    reply
+}
+
+async fn download(client: &mut TestClient, blob_id: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    loop {
+        let request = DownloadRequest {
+            blob_id: blob_id.into(),
+            offset: data.len() as u64,
+        };
+        client.send(request).await;
+        let chunk = client.expect::<DownloadChunk>().await;
+        assert!(chunk.success && chunk.is_intact(), "Bad chunk: {:?}", chunk);
+        data.extend(chunk.data);
+        if data.len() as u64 == chunk.size {
+            return data;
+        }
+    }
+}
+
+#[test]
+fn encodes_chunk_data_per_encoding() {
+    let chunk = UploadChunk::new("abc".into(), 0, vec![0, 1, 2, 255]);
+    let json = Encoding::Json.serialize(&chunk).unwrap();
+    assert!(String::from_utf8(json.clone())
+        .unwrap()
+        .contains(r#""data":"AAEC/w==""#));
+    let msgpack = Encoding::MessagePack.serialize(&chunk).unwrap();
+    assert!(msgpack.windows(6).any(|w| w == [0xc4, 4, 0, 1, 2, 255]));
+
+    for (encoding, bytes) in [(Encoding::Json, json), (Encoding::MessagePack, msgpack)] {
+        let decoded: UploadChunk = encoding.deserialize(&bytes).unwrap();
+        assert_eq!(decoded.data, chunk.data);
+        assert_eq!(decoded.checksum, chunk.checksum);
+    }
+}
+
+#[tokio::test]
+async fn uploads_and_downloads_in_chunks() {
+    let (server, _dir) = start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let data = file(5000);
+
+    alice.send(request(&data, None)).await;
+    let reply = alice.expect::<UploadReply>().await;
+    assert!(reply.success);
+    assert_eq!(reply.offset, 0);
+    assert!(reply.chunk_size > 0 && reply.chunk_size < 1024);
+
+    let reply = send_chunks(&mut alice, &data, reply, data.len()).await;
+    let blob_id = reply.blob_id.expect("Upload incomplete");
+
+    let content = Content::File {
+        blob_id: blob_id.clone(),
+        name: "notes.txt".into(),
+        size: data.len() as u64,
+        mime: "text/plain".into(),
+    };
+    alice.send(ClientMessage::new(content, "bob".into())).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    alice.expect::<ServerMessage>().await;
+    let message = bob.expect::<ServerMessage>().await;
+    match message.content {
+        Content::File {
+            blob_id: received, ..
+        } => assert_eq!(received, blob_id),
+        content => panic!("Unexpected content: {:?}", content),
+    }
+    assert_eq!(download(&mut bob, &blob_id).await, data);
+}
+
+#[tokio::test]
+async fn resumes_interrupted_upload() {
+    let (server, _dir) = start().await;
+    let data = file(3000);
+
+    let mut alice = server.login("alice").await;
+    alice.send(request(&data, None)).await;
+    let reply = alice.expect::<UploadReply>().await;
+    let reply = send_chunks(&mut alice, &data, reply, 1000).await;
+    let transfer_id = reply.transfer_id.clone();
+    drop(alice);
+    // Lets the server notice the disconnection before logging in again.
+    time::sleep(Duration::from_millis(100)).await;
+
+    let mut alice = server.login("alice").await;
+    alice.send(request(&data, transfer_id)).await;
+    let reply = alice.expect::<UploadReply>().await;
+    assert!(reply.success);
+    assert!(reply.offset >= 1000 && reply.offset < data.len() as u64);
+
+    let reply = send_chunks(&mut alice, &data, reply, data.len()).await;
+    let blob_id = reply.blob_id.expect("Upload incomplete");
+    assert_eq!(download(&mut alice, &blob_id).await, data);
+}
+
+#[tokio::test]
+async fn removes_idle_uploads() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        max_payload_length: 1024,
+        blob_dir: dir.path().into(),
+        upload_idle_timeout: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let server = TestServer::start_with_config(config).await;
+    let data = file(3000);
+
+    let mut alice = server.login("alice").await;
+    alice.send(request(&data, None)).await;
+    let reply = alice.expect::<UploadReply>().await;
+    let reply = send_chunks(&mut alice, &data, reply, 1000).await;
+    let transfer_id = reply.transfer_id.clone();
+
+    time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(
+        std::fs::read_dir(dir.path().join("partial"))
+            .unwrap()
+            .count(),
+        0
+    );
+    alice.send(request(&data, transfer_id)).await;
+    let reply = alice.expect::<UploadReply>().await;
+    assert!(!reply.success);
+    assert_eq!(reply.code, Some(ErrorCode::TransferNotFound));
+}
+
+#[tokio::test]
+async fn rejects_bad_chunks() {
+    let (server, _dir) = start().await;
+    let mut alice = server.login("alice").await;
+    let data = file(1000);
+
+    alice.send(request(&data, None)).await;
+    let transfer_id = alice.expect::<UploadReply>().await.transfer_id.unwrap();
+
+    let mut chunk = UploadChunk::new(transfer_id.clone(), 0, data[..100].to_vec());
+    chunk.checksum ^= 1;
+    alice.send(chunk).await;
+    let reply = alice.expect::<UploadReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::ChecksumMismatch));
+
+    alice
+        .send(UploadChunk::new(
+            transfer_id.clone(),
+            100,
+            data[100..200].to_vec(),
+        ))
+        .await;
+    let reply = alice.expect::<UploadReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::OffsetMismatch));
+
+    // Transfers belong to their uploader.
+    let mut bob = server.login("bob").await;
+    bob.send(UploadChunk::new(transfer_id, 0, data[..100].to_vec()))
+        .await;
+    let reply = bob.expect::<UploadReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::TransferNotFound));
+}
+
+#[tokio::test]
+async fn rejects_corrupted_file() {
+    let (server, _dir) = start().await;
+    let mut alice = server.login("alice").await;
+    let data = file(300);
+    let mut upload = request(&data, None);
+    upload.checksum = format!("{:x}", Sha256::digest(b"something else"));
+
+    alice.send(upload).await;
+    let transfer_id = alice.expect::<UploadReply>().await.transfer_id.unwrap();
+    alice.send(UploadChunk::new(transfer_id, 0, data)).await;
+    let reply = alice.expect::<UploadReply>().await;
+    assert_eq!(reply.code, Some(ErrorCode::ChecksumMismatch));
+    assert!(reply.blob_id.is_none());
+}
+
+#[tokio::test]
+async fn rejects_unknown_blob() {
+    let (server, _dir) = start().await;
+    let mut alice = server.login("alice").await;
+    for blob_id in ["abcdef", "../partial"] {
+        alice
+            .send(DownloadRequest {
+                blob_id: blob_id.into(),
+                offset: 0,
+            })
+            .await;
+        let chunk = alice.expect::<DownloadChunk>().await;
+        assert_eq!(chunk.code, Some(ErrorCode::BlobNotFound));
+    }
+}
+
+#[tokio::test]
+async fn chat_flows_between_chunks() {
+    let (server, _dir) = start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let data = file(2000);
+
+    alice.send(request(&data, None)).await;
+    let reply = alice.expect::<UploadReply>().await;
+    let transfer_id = reply.transfer_id.clone().unwrap();
+    let chunk = data[..reply.chunk_size].to_vec();
+    alice.send(UploadChunk::new(transfer_id, 0, chunk)).await;
+    alice
+        .send(ClientMessage::new(Content::Text("hi".into()), "bob".into()))
+        .await;
+
+    assert!(alice.expect::<UploadReply>().await.success);
+    assert!(alice.expect::<MessageReply>().await.success);
+    bob.expect_message_from("alice", support::TIMEOUT).await;
+}
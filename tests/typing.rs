@@ -0,0 +1,97 @@
+mod support;
+
+use std::time::Duration;
+
+use sine_chat::{
+    handler::Config,
+    message::{
+        ClientMessage, Content, MessageReply, PeerTyping, ServerMessage, Typing, TypingState,
+    },
+};
+use tokio::time;
+
+use support::{TestClient, TestServer};
+
+async fn start(throttle: Duration, timeout: Duration) -> TestServer {
+    TestServer::start_with_config(Config {
+        typing_throttle: throttle,
+        typing_timeout: timeout,
+        ..Default::default()
+    })
+    .await
+}
+
+async fn typing(client: &mut TestClient, receiver: &str, state: TypingState) {
+    let typing = Typing {
+        receiver: receiver.into(),
+        state,
+    };
+    client.send(typing).await
+}
+
+async fn expect_typing(client: &mut TestClient, sender: &str, state: TypingState) {
+    let event = client.expect::<PeerTyping>().await;
+    assert_eq!(event.sender, sender);
+    assert_eq!(event.state, state);
+}
+
+#[tokio::test]
+async fn forwards_typing_without_reply() {
+    let server = TestServer::start().await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    typing(&mut alice, "bob", TypingState::Typing).await;
+    expect_typing(&mut bob, "alice", TypingState::Typing).await;
+    typing(&mut alice, "bob", TypingState::Stopped).await;
+    expect_typing(&mut bob, "alice", TypingState::Stopped).await;
+
+    // Nobody offline to tell, and stopping twice says nothing new.
+    typing(&mut alice, "carol", TypingState::Typing).await;
+    typing(&mut alice, "bob", TypingState::Stopped).await;
+    alice.expect_silence(Duration::from_millis(100)).await;
+    bob.expect_silence(Duration::from_millis(100)).await;
+}
+
+#[tokio::test]
+async fn throttles_per_sender() {
+    let server = start(Duration::from_millis(300), Duration::from_secs(10)).await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+    let mut carol = server.login("carol").await;
+
+    for _ in 0..5 {
+        typing(&mut alice, "bob", TypingState::Typing).await;
+    }
+    typing(&mut carol, "bob", TypingState::Typing).await;
+    expect_typing(&mut bob, "alice", TypingState::Typing).await;
+    expect_typing(&mut bob, "carol", TypingState::Typing).await;
+    bob.expect_silence(Duration::from_millis(100)).await;
+
+    time::sleep(Duration::from_millis(300)).await;
+    typing(&mut alice, "bob", TypingState::Typing).await;
+    expect_typing(&mut bob, "alice", TypingState::Typing).await;
+}
+
+#[tokio::test]
+async fn expires_typing() {
+    let server = start(Duration::from_millis(50), Duration::from_millis(200)).await;
+    let mut alice = server.login("alice").await;
+    let mut bob = server.login("bob").await;
+
+    typing(&mut alice, "bob", TypingState::Typing).await;
+    expect_typing(&mut bob, "alice", TypingState::Typing).await;
+    let event = bob
+        .expect_within::<PeerTyping>(Duration::from_millis(500))
+        .await;
+    assert_eq!(event.state, TypingState::Stopped);
+
+    // Sending the message ends typing without a separate event.
+    typing(&mut alice, "bob", TypingState::Typing).await;
+    expect_typing(&mut bob, "alice", TypingState::Typing).await;
+    let message = ClientMessage::new(Content::Text("hi".into()), "bob".into());
+    alice.send(message).await;
+    assert!(alice.expect::<MessageReply>().await.success);
+    bob.expect::<ServerMessage>().await;
+    bob.expect_silence(Duration::from_millis(300)).await;
+}